@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::IpAddr;
+
+/// An input line asking for every address of an AS
+pub enum AsnSelector {
+    /// `AS64500`
+    Asn(u32),
+    /// `org:Example Corp`, matched against the AS descriptions
+    Org(String),
+}
+
+impl AsnSelector {
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if let Some(org) = line.strip_prefix("org:").or_else(|| line.strip_prefix("ORG:")) {
+            let org = org.trim().trim_matches('"');
+            return (!org.is_empty()).then(|| AsnSelector::Org(org.to_lowercase()));
+        }
+        let digits = line.strip_prefix("AS").or_else(|| line.strip_prefix("as"))?;
+        digits.parse().ok().map(AsnSelector::Asn)
+    }
+}
+
+/// One announced range of an AS
+pub struct AsnRange {
+    pub start: IpAddr,
+    pub end: IpAddr,
+}
+
+impl AsnRange {
+    pub fn size(&self) -> u128 {
+        match (self.start, self.end) {
+            (IpAddr::V4(s), IpAddr::V4(e)) => (u32::from(e) as u128).saturating_sub(u32::from(s) as u128) + 1,
+            (IpAddr::V6(s), IpAddr::V6(e)) => u128::from(e).saturating_sub(u128::from(s)).saturating_add(1),
+            _ => 0,
+        }
+    }
+
+    pub fn ips(&self) -> Box<dyn Iterator<Item = IpAddr>> {
+        match (self.start, self.end) {
+            (IpAddr::V4(s), IpAddr::V4(e)) => Box::new((u32::from(s)..=u32::from(e)).map(|n| IpAddr::from(n.to_be_bytes()))),
+            (IpAddr::V6(s), IpAddr::V6(e)) => Box::new((u128::from(s)..=u128::from(e)).map(|n| IpAddr::from(n.to_be_bytes()))),
+            _ => Box::new(std::iter::empty()),
+        }
+    }
+}
+
+/// Offline ASN-to-prefix database in iptoasn TSV format:
+/// `range_start  range_end  AS_number  country_code  AS_description`
+pub struct AsnDb {
+    ranges: HashMap<u32, Vec<AsnRange>>,
+    descriptions: HashMap<u32, String>,
+}
+
+impl AsnDb {
+    pub fn load(path: &str) -> io::Result<Self> {
+        let mut db = AsnDb {
+            ranges: HashMap::new(),
+            descriptions: HashMap::new(),
+        };
+        let reader = BufReader::new(File::open(path)?);
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split('\t').collect();
+            let parsed = match fields.as_slice() {
+                [start, end, asn, rest @ ..] => start
+                    .parse::<IpAddr>()
+                    .ok()
+                    .zip(end.parse::<IpAddr>().ok())
+                    .zip(asn.trim_start_matches("AS").parse::<u32>().ok())
+                    .map(|((start, end), asn)| (start, end, asn, rest.get(1).copied().unwrap_or(""))),
+                _ => None,
+            };
+            let Some((start, end, asn, description)) = parsed else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: expected an iptoasn TSV line", path, n + 1),
+                ));
+            };
+            // AS0 marks ranges that are not routed
+            if asn == 0 {
+                continue;
+            }
+            db.ranges.entry(asn).or_default().push(AsnRange { start, end });
+            db.descriptions.entry(asn).or_insert_with(|| description.to_string());
+        }
+        Ok(db)
+    }
+
+    /// The ASNs a selector refers to, in ascending order
+    pub fn select(&self, selector: &AsnSelector) -> Vec<u32> {
+        let mut asns: Vec<u32> = match selector {
+            AsnSelector::Asn(asn) => self.ranges.contains_key(asn).then_some(*asn).into_iter().collect(),
+            AsnSelector::Org(org) => self
+                .descriptions
+                .iter()
+                .filter(|(_, description)| description.to_lowercase().contains(org.as_str()))
+                .map(|(asn, _)| *asn)
+                .collect(),
+        };
+        asns.sort();
+        asns
+    }
+
+    pub fn ranges(&self, asn: u32) -> &[AsnRange] {
+        self.ranges.get(&asn).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
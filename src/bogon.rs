@@ -0,0 +1,111 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Name the special-purpose range `ip` belongs to, or `None` for a public
+/// address. InternetDB has no data for any of these, so querying them only
+/// burns a request.
+pub fn classify(ip: IpAddr) -> Option<&'static str> {
+    match ip {
+        IpAddr::V4(v4) => classify_v4(v4),
+        IpAddr::V6(v6) => classify_v6(v6),
+    }
+}
+
+fn classify_v4(ip: Ipv4Addr) -> Option<&'static str> {
+    let [a, b, c, _] = ip.octets();
+    match (a, b, c) {
+        (0, _, _) => Some("unspecified (0.0.0.0/8)"),
+        (10, _, _) => Some("private (RFC1918)"),
+        (172, 16..=31, _) => Some("private (RFC1918)"),
+        (192, 168, _) => Some("private (RFC1918)"),
+        (127, _, _) => Some("loopback"),
+        (100, 64..=127, _) => Some("CGNAT (100.64.0.0/10)"),
+        (169, 254, _) => Some("link-local"),
+        (192, 0, 0) => Some("IETF protocol assignment (192.0.0.0/24)"),
+        (192, 0, 2) | (198, 51, 100) | (203, 0, 113) => Some("documentation"),
+        (198, 18..=19, _) => Some("benchmarking (198.18.0.0/15)"),
+        (224..=239, _, _) => Some("multicast"),
+        (240..=255, _, _) => Some("reserved (240.0.0.0/4)"),
+        _ => None,
+    }
+}
+
+fn classify_v6(ip: Ipv6Addr) -> Option<&'static str> {
+    if ip.is_unspecified() {
+        return Some("unspecified");
+    }
+    if ip.is_loopback() {
+        return Some("loopback");
+    }
+    // IPv4-mapped addresses (::ffff:a.b.c.d) inherit the class of the IPv4 address
+    if let Some(v4) = ip.to_ipv4_mapped() {
+        return classify_v4(v4);
+    }
+    let segments = ip.segments();
+    match segments[0] {
+        s if s & 0xfe00 == 0xfc00 => Some("unique local (fc00::/7)"),
+        s if s & 0xffc0 == 0xfe80 => Some("link-local"),
+        s if s & 0xff00 == 0xff00 => Some("multicast"),
+        0x2001 if segments[1] == 0x0db8 => Some("documentation (2001:db8::/32)"),
+        0x0100 if segments[1..4] == [0, 0, 0] => Some("discard-only (100::/64)"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(ip: &str) -> Option<&'static str> {
+        classify(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn ipv4_boundaries() {
+        assert_eq!(class("172.15.255.255"), None);
+        assert_eq!(class("172.16.0.0"), Some("private (RFC1918)"));
+        assert_eq!(class("172.31.255.255"), Some("private (RFC1918)"));
+        assert_eq!(class("172.32.0.0"), None);
+
+        assert_eq!(class("100.63.255.255"), None);
+        assert_eq!(class("100.64.0.0"), Some("CGNAT (100.64.0.0/10)"));
+        assert_eq!(class("100.127.255.255"), Some("CGNAT (100.64.0.0/10)"));
+        assert_eq!(class("100.128.0.0"), None);
+
+        assert_eq!(class("169.254.1.1"), Some("link-local"));
+        assert_eq!(class("169.253.1.1"), None);
+        assert_eq!(class("198.18.0.1"), Some("benchmarking (198.18.0.0/15)"));
+        assert_eq!(class("198.19.255.255"), Some("benchmarking (198.18.0.0/15)"));
+        assert_eq!(class("198.20.0.0"), None);
+        assert_eq!(class("223.255.255.255"), None);
+        assert_eq!(class("224.0.0.1"), Some("multicast"));
+        assert_eq!(class("239.255.255.255"), Some("multicast"));
+        assert_eq!(class("240.0.0.1"), Some("reserved (240.0.0.0/4)"));
+        assert_eq!(class("255.255.255.255"), Some("reserved (240.0.0.0/4)"));
+        assert_eq!(class("192.0.2.1"), Some("documentation"));
+        assert_eq!(class("192.0.3.1"), None);
+    }
+
+    #[test]
+    fn ipv6_ranges() {
+        assert_eq!(class("::ffff:10.0.0.1"), Some("private (RFC1918)"));
+        assert_eq!(class("::ffff:8.8.8.8"), None);
+        assert_eq!(class("fc00::1"), Some("unique local (fc00::/7)"));
+        assert_eq!(class("fdff::1"), Some("unique local (fc00::/7)"));
+        assert_eq!(class("fe00::1"), None);
+        assert_eq!(class("fe80::1"), Some("link-local"));
+        assert_eq!(class("febf::1"), Some("link-local"));
+        assert_eq!(class("fec0::1"), None);
+        assert_eq!(class("2001:db8::1"), Some("documentation (2001:db8::/32)"));
+        assert_eq!(class("2001:db9::1"), None);
+        assert_eq!(class("::1"), Some("loopback"));
+        assert_eq!(class("::"), Some("unspecified"));
+        assert_eq!(class("ff02::1"), Some("multicast"));
+    }
+
+    #[test]
+    fn public_addresses() {
+        assert_eq!(class("8.8.8.8"), None);
+        assert_eq!(class("93.184.216.34"), None);
+        assert_eq!(class("2606:4700:4700::1111"), None);
+    }
+}
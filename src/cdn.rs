@@ -0,0 +1,108 @@
+use ipnet::IpNet;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+// Bundled CDN/WAF dataset. Ranges come from the providers' published lists;
+// providers that don't publish stable ranges are matched on CNAME suffix only.
+const CDN_RANGES: &[(&str, &[&str])] = &[
+    (
+        "cloudflare",
+        &[
+            "173.245.48.0/20", "103.21.244.0/22", "103.22.200.0/22", "103.31.4.0/22", "141.101.64.0/18",
+            "108.162.192.0/18", "190.93.240.0/20", "188.114.96.0/20", "197.234.240.0/22", "198.41.128.0/17",
+            "162.158.0.0/15", "104.16.0.0/13", "104.24.0.0/14", "172.64.0.0/13", "131.0.72.0/22",
+            "2400:cb00::/32", "2606:4700::/32", "2803:f800::/32", "2405:b500::/32", "2405:8100::/32",
+            "2a06:98c0::/29", "2c0f:f248::/32",
+        ],
+    ),
+    (
+        "fastly",
+        &[
+            "23.235.32.0/20", "43.249.72.0/22", "103.244.50.0/24", "103.245.222.0/23", "103.245.224.0/24",
+            "104.156.80.0/20", "140.248.64.0/18", "140.248.128.0/17", "146.75.0.0/17", "151.101.0.0/16",
+            "157.52.64.0/18", "167.82.0.0/17", "167.82.128.0/20", "167.82.160.0/20", "167.82.224.0/20",
+            "172.111.64.0/18", "185.31.16.0/22", "199.27.72.0/21", "199.232.0.0/16",
+            "2a04:4e40::/32", "2a04:4e42::/32",
+        ],
+    ),
+    (
+        "akamai",
+        &[
+            "2.16.0.0/13", "23.0.0.0/12", "23.32.0.0/11", "23.64.0.0/14", "23.192.0.0/11", "72.246.0.0/15",
+            "88.221.0.0/16", "96.16.0.0/15", "96.6.0.0/15", "104.64.0.0/10", "184.24.0.0/13", "184.50.0.0/15",
+            "184.84.0.0/14",
+        ],
+    ),
+    (
+        "imperva",
+        &[
+            "199.83.128.0/21", "198.143.32.0/19", "149.126.72.0/21", "103.28.248.0/22", "45.64.64.0/22",
+            "185.11.124.0/22", "192.230.64.0/18", "107.154.0.0/16", "45.60.0.0/16", "45.223.0.0/16",
+            "2a02:e980::/29",
+        ],
+    ),
+    (
+        "sucuri",
+        &["192.88.134.0/23", "185.93.228.0/22", "66.248.200.0/22", "208.109.0.0/22", "2a02:fe80::/29"],
+    ),
+];
+
+const CDN_CNAME_SUFFIXES: &[(&str, &[&str])] = &[
+    ("cloudflare", &["cdn.cloudflare.net", "cloudflare.net"]),
+    ("fastly", &["fastly.net", "fastlylb.net"]),
+    (
+        "akamai",
+        &["akamai.net", "akamaiedge.net", "akamaihd.net", "akamaized.net", "akamaitechnologies.com", "edgekey.net", "edgesuite.net"],
+    ),
+    ("cloudfront", &["cloudfront.net"]),
+    ("imperva", &["incapdns.net", "impervadns.net"]),
+    ("sucuri", &["sucuri.net"]),
+    ("azure", &["azureedge.net", "azurefd.net", "trafficmanager.net"]),
+    ("edgio", &["llnwd.net", "edgecastcdn.net", "systemcdn.net"]),
+    ("stackpath", &["stackpathdns.com", "stackpathcdn.com"]),
+    ("bunnycdn", &["b-cdn.net"]),
+    ("cdn77", &["cdn77.org"]),
+    ("keycdn", &["kxcdn.com"]),
+];
+
+/// `CDN_RANGES` parsed once, on first use
+fn networks() -> &'static [(&'static str, IpNet)] {
+    static NETWORKS: OnceLock<Vec<(&'static str, IpNet)>> = OnceLock::new();
+    NETWORKS.get_or_init(|| {
+        CDN_RANGES
+            .iter()
+            .flat_map(|(provider, ranges)| ranges.iter().filter_map(move |range| Some((*provider, range.parse::<IpNet>().ok()?))))
+            .collect()
+    })
+}
+
+/// Name the CDN/WAF provider whose published ranges contain `ip`
+pub fn match_ip(ip: IpAddr) -> Option<&'static str> {
+    networks().iter().find(|(_, net)| net.contains(&ip)).map(|(provider, _)| *provider)
+}
+
+/// Name the CDN/WAF provider any name in a CNAME chain points into
+pub fn match_cnames(cnames: &[String]) -> Option<&'static str> {
+    cnames.iter().find_map(|name| {
+        CDN_CNAME_SUFFIXES.iter().find_map(|(provider, suffixes)| {
+            suffixes
+                .iter()
+                .any(|suffix| crate::scope::matches_domain(name, suffix))
+                .then_some(*provider)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_bundled_range_parses() {
+        let total: usize = CDN_RANGES.iter().map(|(_, ranges)| ranges.len()).sum();
+        assert_eq!(networks().len(), total);
+        assert_eq!(match_ip("104.16.1.1".parse().unwrap()), Some("cloudflare"));
+        assert_eq!(match_ip("2a04:4e42::1".parse().unwrap()), Some("fastly"));
+        assert_eq!(match_ip("192.0.2.1".parse().unwrap()), None);
+    }
+}
@@ -0,0 +1,281 @@
+use crate::record::HostRecord;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Vendor, product and version pulled out of a CPE string
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Product {
+    pub vendor: String,
+    pub product: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl Product {
+    /// Parse a CPE 2.3 formatted string (`cpe:2.3:a:openbsd:openssh:7.4:*:...`)
+    /// or a CPE 2.2 URI (`cpe:/a:openbsd:openssh:7.4`)
+    pub fn parse(cpe: &str) -> Option<Self> {
+        let fields = if let Some(rest) = cpe.strip_prefix("cpe:2.3:") {
+            split_escaped(rest)
+        } else {
+            split_escaped(cpe.strip_prefix("cpe:/")?)
+        };
+        // part:vendor:product:version:...
+        let value = |i: usize| fields.get(i).map(String::as_str).filter(|v| !v.is_empty() && *v != "*" && *v != "-");
+        Some(Product {
+            vendor: value(1)?.to_lowercase(),
+            product: value(2)?.to_lowercase(),
+            version: value(3).map(str::to_string),
+        })
+    }
+}
+
+/// Split on `:` while honouring CPE 2.3 backslash escapes
+fn split_escaped(s: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    fields.last_mut().unwrap().push(escaped);
+                }
+            }
+            ':' => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+/// A `--product` filter such as `nginx`, `openssh<8` or `apache:http_server>=2.4.49`
+#[derive(Clone)]
+pub struct ProductFilter {
+    vendor: Option<String>,
+    product: String,
+    constraint: Option<(Ordering, bool, String)>,
+}
+
+impl ProductFilter {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, constraint) = match spec.find(['<', '>', '=']) {
+            Some(i) => {
+                let (name, rest) = spec.split_at(i);
+                // (ordering, also accept equal, version)
+                let (ordering, or_equal, version) = if let Some(v) = rest.strip_prefix("<=") {
+                    (Ordering::Less, true, v)
+                } else if let Some(v) = rest.strip_prefix(">=") {
+                    (Ordering::Greater, true, v)
+                } else if let Some(v) = rest.strip_prefix('<') {
+                    (Ordering::Less, false, v)
+                } else if let Some(v) = rest.strip_prefix('>') {
+                    (Ordering::Greater, false, v)
+                } else {
+                    (Ordering::Equal, true, rest.trim_start_matches('='))
+                };
+                if version.trim().is_empty() {
+                    return Err(format!("missing version in product filter '{}'", spec));
+                }
+                (name, Some((ordering, or_equal, version.trim().to_string())))
+            }
+            None => (spec, None),
+        };
+        let name = name.trim().to_lowercase();
+        let (vendor, product) = match name.split_once(':') {
+            Some((vendor, product)) => (Some(vendor.to_string()), product.to_string()),
+            None => (None, name),
+        };
+        if product.is_empty() {
+            return Err(format!("missing product name in product filter '{}'", spec));
+        }
+        Ok(ProductFilter { vendor, product, constraint })
+    }
+
+    /// Products without a version never satisfy a version constraint
+    pub fn matches(&self, product: &Product) -> bool {
+        if product.product != self.product || self.vendor.as_ref().is_some_and(|v| *v != product.vendor) {
+            return false;
+        }
+        match (&self.constraint, &product.version) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some((ordering, or_equal, wanted)), Some(version)) => {
+                let cmp = compare_versions(version, wanted);
+                cmp == *ordering || (*or_equal && cmp == Ordering::Equal)
+            }
+        }
+    }
+}
+
+/// Compare dotted versions piece by piece, numbers numerically, so that
+/// `7.4p1 < 8` and `1.10 > 1.9`. Missing numeric pieces count as zero.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a, b) = (version_pieces(a), version_pieces(b));
+    for i in 0..a.len().max(b.len()) {
+        let cmp = match (a.get(i), b.get(i)) {
+            (Some(Piece::Num(x)), Some(Piece::Num(y))) => x.cmp(y),
+            (Some(Piece::Text(x)), Some(Piece::Text(y))) => x.cmp(y),
+            (Some(Piece::Num(_)), Some(Piece::Text(_))) => Ordering::Greater,
+            (Some(Piece::Text(_)), Some(Piece::Num(_))) => Ordering::Less,
+            (Some(Piece::Num(x)), None) => x.cmp(&0),
+            (None, Some(Piece::Num(y))) => 0.cmp(y),
+            (Some(Piece::Text(_)), None) => Ordering::Greater,
+            (None, Some(Piece::Text(_))) => Ordering::Less,
+            (None, None) => Ordering::Equal,
+        };
+        if cmp != Ordering::Equal {
+            return cmp;
+        }
+    }
+    Ordering::Equal
+}
+
+enum Piece {
+    Num(u64),
+    Text(String),
+}
+
+fn version_pieces(version: &str) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut current = String::new();
+    let flush = |current: &mut String, pieces: &mut Vec<Piece>| {
+        if !current.is_empty() {
+            pieces.push(match current.parse() {
+                Ok(n) => Piece::Num(n),
+                Err(_) => Piece::Text(current.to_lowercase()),
+            });
+            current.clear();
+        }
+    };
+    for c in version.chars() {
+        let switches = current.chars().last().is_some_and(|last| last.is_ascii_digit() != c.is_ascii_digit());
+        if !c.is_ascii_alphanumeric() || switches {
+            flush(&mut current, &mut pieces);
+        }
+        if c.is_ascii_alphanumeric() {
+            current.push(c);
+        }
+    }
+    flush(&mut current, &mut pieces);
+    pieces
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Write one `host,ip,vendor,product,version` row per product found on an IP
+pub fn write_csv(path: &str, records: &HashMap<String, HostRecord>) -> io::Result<usize> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "host,ip,vendor,product,version")?;
+    let mut hosts: Vec<&HostRecord> = records.values().collect();
+    hosts.sort_by(|a, b| a.host.cmp(&b.host));
+    let mut rows = 0;
+    for record in hosts {
+        for ip_record in &record.ips {
+            for product in &ip_record.products {
+                let fields = [
+                    record.host.as_str(),
+                    ip_record.result.ip.as_str(),
+                    product.vendor.as_str(),
+                    product.product.as_str(),
+                    product.version.as_deref().unwrap_or(""),
+                ];
+                let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                writeln!(file, "{}", fields.join(","))?;
+                rows += 1;
+            }
+        }
+    }
+    file.flush()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn product(cpe: &str) -> Product {
+        Product::parse(cpe).unwrap()
+    }
+
+    fn matches(filter: &str, cpe: &str) -> bool {
+        ProductFilter::parse(filter).unwrap().matches(&product(cpe))
+    }
+
+    #[test]
+    fn parse_cpe_23_and_22() {
+        let p = product("cpe:2.3:a:OpenBSD:OpenSSH:7.4p1:*:*:*:*:*:*:*");
+        assert_eq!((p.vendor.as_str(), p.product.as_str(), p.version.as_deref()), ("openbsd", "openssh", Some("7.4p1")));
+        let p = product("cpe:/a:nginx:nginx:1.18.0");
+        assert_eq!((p.vendor.as_str(), p.product.as_str(), p.version.as_deref()), ("nginx", "nginx", Some("1.18.0")));
+        let p = product("cpe:/a:nginx:nginx");
+        assert_eq!(p.version, None);
+        let p = product("cpe:2.3:a:microsoft:iis:-:*:*:*:*:*:*:*");
+        assert_eq!(p.version, None);
+        assert!(Product::parse("cpe:2.3:a:*:nginx:1.0").is_none());
+        assert!(Product::parse("nginx").is_none());
+    }
+
+    #[test]
+    fn parse_cpe_23_escaped_colons() {
+        let p = product(r"cpe:2.3:a:acme\:labs:web\:server:2.0\:rc1:*:*:*:*:*:*:*");
+        assert_eq!(p.vendor, "acme:labs");
+        assert_eq!(p.product, "web:server");
+        assert_eq!(p.version.as_deref(), Some("2.0:rc1"));
+    }
+
+    #[test]
+    fn version_ordering() {
+        assert_eq!(compare_versions("7.4p1", "8"), Ordering::Less);
+        assert_eq!(compare_versions("8.2p1", "8"), Ordering::Greater);
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("2.4.49", "2.4.49"), Ordering::Equal);
+        assert_eq!(compare_versions("8.0", "8"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0rc1", "1.0"), Ordering::Greater);
+    }
+
+    #[test]
+    fn readme_openssh_example() {
+        assert!(matches("openssh<8", "cpe:2.3:a:openbsd:openssh:7.4p1"));
+        assert!(!matches("openssh<8", "cpe:2.3:a:openbsd:openssh:8.2p1"));
+        // No version, no match against a constraint
+        assert!(!matches("openssh<8", "cpe:/a:openbsd:openssh"));
+        assert!(matches("openssh", "cpe:/a:openbsd:openssh"));
+    }
+
+    #[test]
+    fn inclusive_bounds_and_equality() {
+        let cpe = "cpe:2.3:a:apache:http_server:2.4.49";
+        assert!(matches("http_server>=2.4.49", cpe));
+        assert!(!matches("http_server>2.4.49", cpe));
+        assert!(matches("http_server<=2.4.49", cpe));
+        assert!(!matches("http_server<2.4.49", cpe));
+        assert!(matches("http_server=2.4.49", cpe));
+        assert!(!matches("http_server=2.4.50", cpe));
+        assert!(matches("http_server >= 2.4.1", cpe));
+    }
+
+    #[test]
+    fn vendor_qualified_filters() {
+        let cpe = "cpe:2.3:a:apache:http_server:2.4.49";
+        assert!(matches("apache:http_server>=2.4.49", cpe));
+        assert!(matches("Apache:HTTP_Server", cpe));
+        assert!(!matches("nginx:http_server", cpe));
+        assert!(!matches("apache:tomcat", cpe));
+    }
+
+    #[test]
+    fn invalid_filters() {
+        assert!(ProductFilter::parse("openssh<").is_err());
+        assert!(ProductFilter::parse(">=2.0").is_err());
+        assert!(ProductFilter::parse("apache:").is_err());
+    }
+}
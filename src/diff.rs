@@ -0,0 +1,413 @@
+use crate::dns::ResolutionFailure;
+use crate::record::HostRecord;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+
+/// Compare two scans saved with `--json`
+#[derive(clap::Args)]
+pub struct DiffArgs {
+    /// Earlier scan (JSON lines from --json)
+    pub old: String,
+
+    /// Later scan (JSON lines from --json)
+    pub new: String,
+
+    /// Also save the changes as JSON to this file
+    #[arg(short = 'j', long, value_name = "JSON_FILE")]
+    pub json: Option<String>,
+}
+
+/// A port that opened or closed on a host
+#[derive(Serialize)]
+pub struct PortChange {
+    pub host: String,
+    pub port: u16,
+}
+
+/// A CVE or tag that appeared on a host
+#[derive(Serialize)]
+pub struct HostChange {
+    pub host: String,
+    pub value: String,
+}
+
+/// What changed between two scans. Ports, CVEs and tags are compared per
+/// host across all of its IPs, so a host moving to a new IP with the same
+/// services is not reported.
+#[derive(Serialize, Default)]
+pub struct ScanDiff {
+    pub new_hosts: Vec<String>,
+    pub vanished_hosts: Vec<String>,
+    pub new_ports: Vec<PortChange>,
+    pub closed_ports: Vec<PortChange>,
+    pub new_cves: Vec<HostChange>,
+    pub new_tags: Vec<HostChange>,
+}
+
+impl ScanDiff {
+    pub fn is_empty(&self) -> bool {
+        self.new_hosts.is_empty()
+            && self.vanished_hosts.is_empty()
+            && self.new_ports.is_empty()
+            && self.closed_ports.is_empty()
+            && self.new_cves.is_empty()
+            && self.new_tags.is_empty()
+    }
+
+    pub fn print(&self) {
+        for host in &self.new_hosts {
+            println!("+ host {}", host);
+        }
+        for host in &self.vanished_hosts {
+            println!("- host {}", host);
+        }
+        for change in &self.new_ports {
+            println!("+ port {}", crate::format_host_port(&change.host, change.port));
+        }
+        for change in &self.closed_ports {
+            println!("- port {}", crate::format_host_port(&change.host, change.port));
+        }
+        for change in &self.new_cves {
+            println!("+ cve  {} {}", change.host, change.value);
+        }
+        for change in &self.new_tags {
+            println!("+ tag  {} {}", change.host, change.value);
+        }
+        println!(
+            "{} new hosts, {} vanished hosts, {} new ports, {} closed ports, {} new CVEs, {} new tags",
+            self.new_hosts.len(),
+            self.vanished_hosts.len(),
+            self.new_ports.len(),
+            self.closed_ports.len(),
+            self.new_cves.len(),
+            self.new_tags.len()
+        );
+    }
+}
+
+/// One change between two scans, as sent by `qport watch` and `--webhook`
+#[derive(Serialize)]
+pub struct ChangeEvent {
+    pub timestamp: u64,
+    /// `new_host`, `vanished_host`, `new_port`, `closed_port`, `new_cve` or `new_tag`
+    pub event: &'static str,
+    pub host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// The CVE or tag for `new_cve` and `new_tag`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+impl ChangeEvent {
+    fn new(timestamp: u64, event: &'static str, host: &str) -> Self {
+        ChangeEvent {
+            timestamp,
+            event,
+            host: host.to_string(),
+            port: None,
+            value: None,
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        let target = match self.port {
+            Some(port) => crate::format_host_port(&self.host, port),
+            None => self.host.clone(),
+        };
+        match &self.value {
+            Some(value) => format!("{} {} {} {}", format_utc(self.timestamp), self.event, target, value),
+            None => format!("{} {} {}", format_utc(self.timestamp), self.event, target),
+        }
+    }
+}
+
+impl ScanDiff {
+    pub fn events(&self, timestamp: u64) -> Vec<ChangeEvent> {
+        let mut events = vec![];
+        events.extend(self.new_hosts.iter().map(|host| ChangeEvent::new(timestamp, "new_host", host)));
+        events.extend(self.vanished_hosts.iter().map(|host| ChangeEvent::new(timestamp, "vanished_host", host)));
+        for (kind, changes) in [("new_port", &self.new_ports), ("closed_port", &self.closed_ports)] {
+            events.extend(changes.iter().map(|change| ChangeEvent {
+                port: Some(change.port),
+                ..ChangeEvent::new(timestamp, kind, &change.host)
+            }));
+        }
+        for (kind, changes) in [("new_cve", &self.new_cves), ("new_tag", &self.new_tags)] {
+            events.extend(changes.iter().map(|change| ChangeEvent {
+                value: Some(change.value.clone()),
+                ..ChangeEvent::new(timestamp, kind, &change.host)
+            }));
+        }
+        events
+    }
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ` for a Unix timestamp
+pub fn format_utc(timestamp: u64) -> String {
+    let (days, secs) = ((timestamp / 86400) as i64, timestamp % 86400);
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Ports, CVEs and tags seen on a host across its IPs
+#[derive(Default)]
+struct HostSummary {
+    ports: BTreeSet<u16>,
+    cves: BTreeSet<String>,
+    tags: BTreeSet<String>,
+}
+
+/// Hosts with at least one answered IP, summarized for comparison
+fn summarize(records: &HashMap<String, HostRecord>) -> BTreeMap<&str, HostSummary> {
+    let mut hosts = BTreeMap::new();
+    for record in records.values() {
+        let answered: Vec<_> = record.ips.iter().filter(|ip_record| ip_record.error.is_none()).collect();
+        if answered.is_empty() {
+            continue;
+        }
+        let summary: &mut HostSummary = hosts.entry(record.host.as_str()).or_default();
+        for ip_record in answered {
+            let result = &ip_record.result;
+            summary.ports.extend(result.ports.iter().copied());
+            summary.cves.extend(result.vulns.iter().cloned());
+            summary.tags.extend(result.tags.iter().cloned());
+        }
+    }
+    hosts
+}
+
+/// Values of a host that `other` (the same host in the other scan) lacks
+fn not_in<'a, T: Ord>(values: &'a BTreeSet<T>, other: Option<&'a BTreeSet<T>>) -> impl Iterator<Item = &'a T> {
+    values.iter().filter(move |value| !other.is_some_and(|other| other.contains(*value)))
+}
+
+fn port_changes<'a>(host: &'a str, ports: impl Iterator<Item = &'a u16> + 'a) -> impl Iterator<Item = PortChange> + 'a {
+    ports.map(move |port| PortChange {
+        host: host.to_string(),
+        port: *port,
+    })
+}
+
+fn host_changes<'a>(host: &'a str, values: impl Iterator<Item = &'a String> + 'a) -> impl Iterator<Item = HostChange> + 'a {
+    values.map(move |value| HostChange {
+        host: host.to_string(),
+        value: value.clone(),
+    })
+}
+
+/// Hosts whose every InternetDB query failed, or whose resolution failed for
+/// any reason but NXDOMAIN; their state in that scan is unknown
+fn failed_hosts(records: &HashMap<String, HostRecord>) -> BTreeSet<&str> {
+    records
+        .values()
+        .filter(|record| {
+            let unresolved = record.resolution_error.is_some_and(|failure| failure != ResolutionFailure::NxDomain);
+            let unanswered = !record.ips.is_empty() && record.ips.iter().all(|ip_record| ip_record.error.is_some());
+            unresolved || unanswered
+        })
+        .map(|record| record.host.as_str())
+        .collect()
+}
+
+/// Hosts that failed to resolve or query in either scan are left out, so a
+/// DNS or InternetDB outage doesn't show up as every host vanishing. Only
+/// NXDOMAIN counts as a host being gone.
+pub fn compare(old: &HashMap<String, HostRecord>, new: &HashMap<String, HostRecord>) -> ScanDiff {
+    let (old_failed, new_failed) = (failed_hosts(old), failed_hosts(new));
+    let (old, new) = (summarize(old), summarize(new));
+    let mut diff = ScanDiff::default();
+    for (host, summary) in &new {
+        if old_failed.contains(host) {
+            continue;
+        }
+        let before = old.get(host);
+        if before.is_none() {
+            diff.new_hosts.push(host.to_string());
+        }
+        diff.new_ports.extend(port_changes(host, not_in(&summary.ports, before.map(|b| &b.ports))));
+        diff.new_cves.extend(host_changes(host, not_in(&summary.cves, before.map(|b| &b.cves))));
+        diff.new_tags.extend(host_changes(host, not_in(&summary.tags, before.map(|b| &b.tags))));
+    }
+    for (host, summary) in &old {
+        if new_failed.contains(host) {
+            continue;
+        }
+        let after = new.get(host);
+        if after.is_none() {
+            diff.vanished_hosts.push(host.to_string());
+        }
+        diff.closed_ports.extend(port_changes(host, not_in(&summary.ports, after.map(|a| &a.ports))));
+    }
+    diff
+}
+
+/// Read a `--json` scan back into host records
+pub fn load_scan(path: &str) -> io::Result<HashMap<String, HostRecord>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = HashMap::new();
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: HostRecord = serde_json::from_str(&line)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path, n + 1, e)))?;
+        records.insert(record.host.clone(), record);
+    }
+    Ok(records)
+}
+
+pub fn run(args: &DiffArgs) -> io::Result<()> {
+    let diff = compare(&load_scan(&args.old)?, &load_scan(&args.new)?);
+    diff.print();
+    if let Some(json_filename) = &args.json {
+        let mut json_file = File::create(json_filename)?;
+        writeln!(json_file, "{}", serde_json::to_string(&diff)?)?;
+        println!("Changes saved to: {}", json_filename);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::scan_from_json as scan;
+    use serde_json::json;
+
+    fn port_list(changes: &[PortChange]) -> Vec<(&str, u16)> {
+        changes.iter().map(|c| (c.host.as_str(), c.port)).collect()
+    }
+
+    fn value_list(changes: &[HostChange]) -> Vec<(&str, &str)> {
+        changes.iter().map(|c| (c.host.as_str(), c.value.as_str())).collect()
+    }
+
+    #[test]
+    fn new_and_vanished_hosts() {
+        let old = scan(&[
+            ("a.example.com", json!([{ "ip": "192.0.2.1", "ports": [80], "hostnames": [] }])),
+            ("b.example.com", json!([{ "ip": "192.0.2.2", "ports": [22], "hostnames": [] }])),
+        ]);
+        let new = scan(&[
+            ("a.example.com", json!([{ "ip": "192.0.2.1", "ports": [80], "hostnames": [] }])),
+            ("c.example.com", json!([{ "ip": "192.0.2.3", "ports": [443], "hostnames": [] }])),
+        ]);
+        let diff = compare(&old, &new);
+        assert_eq!(diff.new_hosts, vec!["c.example.com"]);
+        assert_eq!(diff.vanished_hosts, vec!["b.example.com"]);
+        // Ports of new and vanished hosts are reported too
+        assert_eq!(port_list(&diff.new_ports), vec![("c.example.com", 443)]);
+        assert_eq!(port_list(&diff.closed_ports), vec![("b.example.com", 22)]);
+        assert!(compare(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn ports_are_compared_across_ips() {
+        let old = scan(&[(
+            "a.example.com",
+            json!([
+                { "ip": "192.0.2.1", "ports": [80, 443], "hostnames": [] },
+                { "ip": "192.0.2.2", "ports": [22], "hostnames": [] },
+            ]),
+        )]);
+        // Moving to a new IP with the same port is not a change
+        let new = scan(&[(
+            "a.example.com",
+            json!([
+                { "ip": "192.0.2.9", "ports": [80, 8080], "hostnames": [] },
+                { "ip": "192.0.2.2", "ports": [22], "hostnames": [] },
+            ]),
+        )]);
+        let diff = compare(&old, &new);
+        assert!(diff.new_hosts.is_empty() && diff.vanished_hosts.is_empty());
+        assert_eq!(port_list(&diff.new_ports), vec![("a.example.com", 8080)]);
+        assert_eq!(port_list(&diff.closed_ports), vec![("a.example.com", 443)]);
+    }
+
+    #[test]
+    fn new_cves_and_tags() {
+        let old = scan(&[(
+            "a.example.com",
+            json!([{ "ip": "192.0.2.1", "ports": [80], "hostnames": [], "vulns": ["CVE-2021-1"], "tags": ["cdn"] }]),
+        )]);
+        let new = scan(&[(
+            "a.example.com",
+            json!([{ "ip": "192.0.2.1", "ports": [80], "hostnames": [], "vulns": ["CVE-2021-1", "CVE-2023-2"], "tags": ["self-signed"] }]),
+        )]);
+        let diff = compare(&old, &new);
+        assert_eq!(value_list(&diff.new_cves), vec![("a.example.com", "CVE-2023-2")]);
+        assert_eq!(value_list(&diff.new_tags), vec![("a.example.com", "self-signed")]);
+        assert!(diff.new_ports.is_empty() && diff.closed_ports.is_empty());
+
+        let events = diff.events(1_700_000_000);
+        let lines: Vec<String> = events.iter().map(ChangeEvent::to_line).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "2023-11-14T22:13:20Z new_cve a.example.com CVE-2023-2",
+                "2023-11-14T22:13:20Z new_tag a.example.com self-signed",
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_hosts_are_skipped() {
+        let failed = json!([{ "ip": "192.0.2.1", "ports": [], "hostnames": [], "error": "HTTP 503" }]);
+        let answered = json!([{ "ip": "192.0.2.1", "ports": [80, 443], "hostnames": [] }]);
+
+        // Failing now is not vanishing, and recovering is not appearing
+        let diff = compare(&scan(&[("a.example.com", answered.clone())]), &scan(&[("a.example.com", failed.clone())]));
+        assert!(diff.is_empty());
+        let diff = compare(&scan(&[("a.example.com", failed)]), &scan(&[("a.example.com", answered.clone())]));
+        assert!(diff.is_empty());
+
+        // A host with one answered IP still counts
+        let partly = json!([
+            { "ip": "192.0.2.1", "ports": [80], "hostnames": [] },
+            { "ip": "192.0.2.2", "ports": [], "hostnames": [], "error": "timeout" },
+        ]);
+        let diff = compare(&scan(&[("a.example.com", answered)]), &scan(&[("a.example.com", partly)]));
+        assert_eq!(port_list(&diff.closed_ports), vec![("a.example.com", 443)]);
+
+        // A resolver timeout says nothing about the host, NXDOMAIN means it is gone
+        let before = scan(&[("a.example.com", json!([{ "ip": "192.0.2.1", "ports": [80], "hostnames": [] }]))]);
+        let mut timed_out = scan(&[("a.example.com", json!([]))]);
+        timed_out.get_mut("a.example.com").unwrap().resolution_error = Some(ResolutionFailure::Timeout);
+        assert!(compare(&before, &timed_out).is_empty());
+        assert!(compare(&timed_out, &before).is_empty());
+
+        let mut nxdomain = scan(&[("a.example.com", json!([]))]);
+        nxdomain.get_mut("a.example.com").unwrap().resolution_error = Some(ResolutionFailure::NxDomain);
+        let diff = compare(&before, &nxdomain);
+        assert_eq!(diff.vanished_hosts, vec!["a.example.com"]);
+        assert_eq!(port_list(&diff.closed_ports), vec![("a.example.com", 80)]);
+    }
+
+    #[test]
+    fn format_utc_known_timestamps() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(1_700_000_000), "2023-11-14T22:13:20Z");
+        assert_eq!(format_utc(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_utc(1_735_689_599), "2024-12-31T23:59:59Z");
+    }
+}
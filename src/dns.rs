@@ -0,0 +1,417 @@
+use clap::ValueEnum;
+use hickory_resolver::config::{LookupIpStrategy, NameServerConfig, Protocol, ResolverConfig, ResolverOpts};
+use hickory_resolver::error::{ResolveError, ResolveErrorKind};
+use hickory_resolver::proto::error::ProtoErrorKind;
+use hickory_resolver::lookup_ip::LookupIp;
+use hickory_resolver::proto::op::ResponseCode;
+use hickory_resolver::proto::rr::RData;
+use hickory_resolver::TokioAsyncResolver;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How queries are spread across the servers in a `--resolvers` file
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ResolverMode {
+    /// Start each query at the next server, moving on to the others on errors
+    RoundRobin,
+    /// Always start at the first server and only move on when it fails
+    Failover,
+}
+
+/// Why a host could not be resolved
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum ResolutionFailure {
+    /// The name does not exist
+    NxDomain,
+    /// The name exists but has no A/AAAA records
+    NoData,
+    /// The nameserver failed or refused to answer
+    ServFail,
+    Timeout,
+    Other,
+}
+
+impl fmt::Display for ResolutionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ResolutionFailure::NxDomain => "NXDOMAIN",
+            ResolutionFailure::NoData => "no A/AAAA records",
+            ResolutionFailure::ServFail => "SERVFAIL",
+            ResolutionFailure::Timeout => "timeout",
+            ResolutionFailure::Other => "resolution error",
+        };
+        f.write_str(name)
+    }
+}
+
+impl From<&ResolveError> for ResolutionFailure {
+    fn from(err: &ResolveError) -> Self {
+        match err.kind() {
+            ResolveErrorKind::NoRecordsFound { response_code, .. } => match *response_code {
+                ResponseCode::NXDomain => ResolutionFailure::NxDomain,
+                ResponseCode::NoError => ResolutionFailure::NoData,
+                _ => ResolutionFailure::ServFail,
+            },
+            ResolveErrorKind::Timeout => ResolutionFailure::Timeout,
+            ResolveErrorKind::Proto(e) if matches!(e.kind(), ProtoErrorKind::Timeout) => ResolutionFailure::Timeout,
+            _ => ResolutionFailure::Other,
+        }
+    }
+}
+
+/// Addresses a name resolved to, and the CNAME chain followed to get there
+#[derive(Clone)]
+pub struct Resolution {
+    pub ips: Vec<IpAddr>,
+    pub cnames: Vec<String>,
+}
+
+impl Resolution {
+    fn from_lookup(host: &str, lookup: &LookupIp) -> Self {
+        let mut targets = HashMap::new();
+        for record in lookup.as_lookup().records() {
+            if let Some(RData::CNAME(cname)) = record.data() {
+                targets.insert(normalize_name(&record.name().to_utf8()), normalize_name(&cname.0.to_utf8()));
+            }
+        }
+        // Follow the chain from the queried name, guarding against loops
+        let mut cnames = vec![];
+        let mut name = normalize_name(host);
+        while let Some(target) = targets.remove(&name) {
+            cnames.push(target.clone());
+            name = target;
+        }
+        Resolution {
+            ips: lookup.iter().collect(),
+            cnames,
+        }
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_lowercase()
+}
+
+struct CacheEntry {
+    expires: Instant,
+    result: Result<Resolution, ResolutionFailure>,
+}
+
+/// Async DNS backend. Uses the system nameservers unless a resolvers file is
+/// given, in which case each listed server gets its own resolver.
+pub struct DnsResolver {
+    resolvers: Vec<TokioAsyncResolver>,
+    mode: ResolverMode,
+    next: AtomicUsize,
+    cache: Mutex<HashMap<String, CacheEntry>>,
+    cache_hits: AtomicUsize,
+}
+
+impl DnsResolver {
+    pub fn system(timeout: Duration, ip_strategy: LookupIpStrategy) -> io::Result<Self> {
+        let (config, mut opts) = hickory_resolver::system_conf::read_system_conf()?;
+        opts.timeout = timeout;
+        opts.ip_strategy = ip_strategy;
+        Ok(DnsResolver {
+            resolvers: vec![TokioAsyncResolver::tokio(config, opts)],
+            mode: ResolverMode::Failover,
+            next: AtomicUsize::new(0),
+            cache: Mutex::new(HashMap::new()),
+            cache_hits: AtomicUsize::new(0),
+        })
+    }
+
+    /// Build a resolver from a file with one nameserver per line, e.g.
+    /// `8.8.8.8`, `127.0.0.1:5353`, `tcp://9.9.9.9` or
+    /// `tls://1.1.1.1:853#cloudflare-dns.com`. Lines starting with `#` are ignored.
+    pub fn from_file(path: &str, mode: ResolverMode, timeout: Duration, ip_strategy: LookupIpStrategy) -> io::Result<Self> {
+        let mut resolvers = vec![];
+        let reader = BufReader::new(File::open(path)?);
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let server = parse_nameserver(line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: invalid nameserver '{}'", path, n + 1, line),
+                )
+            })?;
+
+            let mut config = ResolverConfig::new();
+            config.add_name_server(server);
+            let mut opts = ResolverOpts::default();
+            opts.timeout = timeout;
+            opts.ip_strategy = ip_strategy;
+            // Retrying is done across servers rather than against the same one
+            opts.attempts = 1;
+            opts.use_hosts_file = false;
+            resolvers.push(TokioAsyncResolver::tokio(config, opts));
+        }
+        if resolvers.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: no nameservers found", path),
+            ));
+        }
+        Ok(DnsResolver {
+            resolvers,
+            mode,
+            next: AtomicUsize::new(0),
+            cache: Mutex::new(HashMap::new()),
+            cache_hits: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn server_count(&self) -> usize {
+        self.resolvers.len()
+    }
+
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits.load(Ordering::Relaxed)
+    }
+
+    /// Resolve `host`, answering from the cache for as long as the records'
+    /// TTL allows. Negative answers are cached for their SOA negative TTL;
+    /// timeouts and server failures are never cached.
+    pub async fn resolve(&self, host: &str) -> Result<Resolution, ResolutionFailure> {
+        let key = normalize_name(host);
+        if let Some(entry) = self.cache.lock().unwrap().get(&key) {
+            if entry.expires > Instant::now() {
+                self.cache_hits.fetch_add(1, Ordering::Relaxed);
+                return entry.result.clone();
+            }
+        }
+
+        let (result, expires) = match self.lookup_ip(host).await {
+            Ok(lookup) => (Ok(Resolution::from_lookup(host, &lookup)), Some(lookup.valid_until())),
+            Err(e) => {
+                let negative_ttl = match e.kind() {
+                    ResolveErrorKind::NoRecordsFound { negative_ttl, .. } => *negative_ttl,
+                    _ => None,
+                };
+                (
+                    Err(ResolutionFailure::from(&e)),
+                    negative_ttl.map(|ttl| Instant::now() + Duration::from_secs(ttl.into())),
+                )
+            }
+        };
+        if let Some(expires) = expires {
+            self.cache.lock().unwrap().insert(
+                key,
+                CacheEntry {
+                    expires,
+                    result: result.clone(),
+                },
+            );
+        }
+        result
+    }
+
+    /// Resolvers in the order the next query should try them
+    fn ordered(&self) -> impl Iterator<Item = &TokioAsyncResolver> {
+        let start = match self.mode {
+            ResolverMode::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            ResolverMode::Failover => 0,
+        };
+        let n = self.resolvers.len();
+        (0..n).map(move |i| &self.resolvers[(start + i) % n])
+    }
+
+    async fn lookup_ip(&self, host: &str) -> Result<LookupIp, ResolveError> {
+        let mut last_err = None;
+        for resolver in self.ordered() {
+            match resolver.lookup_ip(host).await {
+                Ok(lookup) => return Ok(lookup),
+                // A negative answer is an answer, asking another server won't change it
+                Err(e) if matches!(ResolutionFailure::from(&e), ResolutionFailure::NxDomain | ResolutionFailure::NoData) => {
+                    return Err(e)
+                }
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| ResolveError::from("no nameservers configured")))
+    }
+
+    /// Look up the PTR names of `ip`. Failures just mean there are no names.
+    pub async fn reverse(&self, ip: IpAddr) -> Vec<String> {
+        for resolver in self.ordered() {
+            match resolver.reverse_lookup(ip).await {
+                Ok(lookup) => return lookup.iter().map(|ptr| normalize_name(&ptr.0.to_utf8())).collect(),
+                Err(e) if matches!(ResolutionFailure::from(&e), ResolutionFailure::NxDomain | ResolutionFailure::NoData) => {
+                    return vec![]
+                }
+                Err(_) => continue,
+            }
+        }
+        vec![]
+    }
+}
+
+fn parse_nameserver(line: &str) -> Option<NameServerConfig> {
+    let (protocol, rest) = if let Some(rest) = line.strip_prefix("tls://") {
+        (Protocol::Tls, rest)
+    } else if let Some(rest) = line.strip_prefix("tcp://") {
+        (Protocol::Tcp, rest)
+    } else {
+        (Protocol::Udp, line.strip_prefix("udp://").unwrap_or(line))
+    };
+    let (addr, tls_name) = match rest.split_once('#') {
+        Some((addr, name)) => (addr, Some(name.to_string())),
+        None => (rest, None),
+    };
+    let default_port = if protocol == Protocol::Tls { 853 } else { 53 };
+    let socket_addr = addr
+        .parse::<SocketAddr>()
+        .or_else(|_| addr.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, default_port)))
+        .ok()?;
+
+    let mut server = NameServerConfig::new(socket_addr, protocol);
+    server.tls_dns_name = tls_name.or_else(|| (protocol == Protocol::Tls).then(|| socket_addr.ip().to_string()));
+    Some(server)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use hickory_resolver::proto::op::{Message, MessageType};
+    use hickory_resolver::proto::rr::rdata::{A, AAAA};
+    use hickory_resolver::proto::rr::{Record, RecordType};
+    use std::net::Ipv4Addr;
+    use tokio::net::UdpSocket;
+
+    #[test]
+    fn parse_nameserver_forms() {
+        let server = parse_nameserver("8.8.8.8").unwrap();
+        assert_eq!(server.socket_addr, "8.8.8.8:53".parse().unwrap());
+        assert_eq!(server.protocol, Protocol::Udp);
+        assert_eq!(server.tls_dns_name, None);
+
+        let server = parse_nameserver("udp://127.0.0.1:5353").unwrap();
+        assert_eq!(server.socket_addr, "127.0.0.1:5353".parse().unwrap());
+        assert_eq!(server.protocol, Protocol::Udp);
+
+        let server = parse_nameserver("127.0.0.1:5353").unwrap();
+        assert_eq!(server.socket_addr, "127.0.0.1:5353".parse().unwrap());
+        assert_eq!(server.protocol, Protocol::Udp);
+
+        let server = parse_nameserver("tcp://9.9.9.9").unwrap();
+        assert_eq!(server.socket_addr, "9.9.9.9:53".parse().unwrap());
+        assert_eq!(server.protocol, Protocol::Tcp);
+
+        let server = parse_nameserver("tls://1.1.1.1#cloudflare-dns.com").unwrap();
+        assert_eq!(server.socket_addr, "1.1.1.1:853".parse().unwrap());
+        assert_eq!(server.protocol, Protocol::Tls);
+        assert_eq!(server.tls_dns_name.as_deref(), Some("cloudflare-dns.com"));
+
+        let server = parse_nameserver("tls://[2606:4700:4700::1111]:8853").unwrap();
+        assert_eq!(server.socket_addr, "[2606:4700:4700::1111]:8853".parse().unwrap());
+        assert_eq!(server.tls_dns_name.as_deref(), Some("2606:4700:4700::1111"));
+
+        assert!(parse_nameserver("dns.google").is_none());
+        assert!(parse_nameserver("tcp://8.8.8.8:99999").is_none());
+    }
+
+    /// A DNS server on localhost answering every A and AAAA query with `answers`
+    pub(crate) async fn stub(answers: Vec<IpAddr>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0; 512];
+            while let Ok((len, peer)) = socket.recv_from(&mut buf).await {
+                let Ok(request) = Message::from_vec(&buf[..len]) else { continue };
+                let mut response = Message::new();
+                response
+                    .set_id(request.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_available(true);
+                for query in request.queries() {
+                    response.add_query(query.clone());
+                    for answer in &answers {
+                        let rdata = match (query.query_type(), answer) {
+                            (RecordType::A, IpAddr::V4(ip)) => RData::A(A(*ip)),
+                            (RecordType::AAAA, IpAddr::V6(ip)) => RData::AAAA(AAAA(*ip)),
+                            _ => continue,
+                        };
+                        response.add_answer(Record::from_rdata(query.name().clone(), 0, rdata));
+                    }
+                }
+                let _ = socket.send_to(&response.to_vec().unwrap(), peer).await;
+            }
+        });
+        addr
+    }
+
+    /// A socket that receives queries and never answers
+    pub(crate) async fn silent() -> (UdpSocket, SocketAddr) {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        (socket, addr)
+    }
+
+    pub(crate) fn from_servers(name: &str, servers: &[SocketAddr], mode: ResolverMode, ip_strategy: LookupIpStrategy) -> DnsResolver {
+        let path = std::env::temp_dir().join(format!("qport-resolvers-{}-{}.txt", std::process::id(), name));
+        let lines: Vec<String> = servers.iter().map(SocketAddr::to_string).collect();
+        std::fs::write(&path, format!("# test servers\n{}\n", lines.join("\n"))).unwrap();
+        let resolver = DnsResolver::from_file(path.to_str().unwrap(), mode, Duration::from_millis(300), ip_strategy).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        resolver
+    }
+
+    async fn answer(resolver: &DnsResolver, host: &str) -> Option<Vec<IpAddr>> {
+        resolver.resolve(host).await.ok().map(|resolution| resolution.ips)
+    }
+
+    fn ip(last: u8) -> Vec<IpAddr> {
+        vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, last))]
+    }
+
+    #[tokio::test]
+    async fn round_robin_spreads_queries() {
+        let servers = [stub(ip(1)).await, stub(ip(2)).await];
+        let resolver = from_servers("round-robin", &servers, ResolverMode::RoundRobin, LookupIpStrategy::Ipv4Only);
+        assert_eq!(resolver.server_count(), 2);
+        assert_eq!(answer(&resolver, "a.test.").await, Some(ip(1)));
+        assert_eq!(answer(&resolver, "b.test.").await, Some(ip(2)));
+        assert_eq!(answer(&resolver, "c.test.").await, Some(ip(1)));
+    }
+
+    #[tokio::test]
+    async fn failover_sticks_to_first_server() {
+        let servers = [stub(ip(1)).await, stub(ip(2)).await];
+        let resolver = from_servers("failover", &servers, ResolverMode::Failover, LookupIpStrategy::Ipv4Only);
+        assert_eq!(answer(&resolver, "a.test.").await, Some(ip(1)));
+        assert_eq!(answer(&resolver, "b.test.").await, Some(ip(1)));
+    }
+
+    #[tokio::test]
+    async fn dead_server_moves_to_next() {
+        let (_silent, dead) = silent().await;
+        let live = stub(ip(3)).await;
+
+        let resolver = from_servers("failover-dead", &[dead, live], ResolverMode::Failover, LookupIpStrategy::Ipv4Only);
+        assert_eq!(answer(&resolver, "a.test.").await, Some(ip(3)));
+        assert_eq!(answer(&resolver, "b.test.").await, Some(ip(3)));
+
+        let resolver = from_servers("round-robin-dead", &[dead, live], ResolverMode::RoundRobin, LookupIpStrategy::Ipv4Only);
+        assert_eq!(answer(&resolver, "a.test.").await, Some(ip(3)));
+        assert_eq!(answer(&resolver, "b.test.").await, Some(ip(3)));
+    }
+
+    #[tokio::test]
+    async fn silent_server_times_out() {
+        let (_silent, addr) = silent().await;
+        let resolver = from_servers("silent", &[addr], ResolverMode::Failover, LookupIpStrategy::Ipv4Only);
+        assert!(matches!(resolver.resolve("a.test.").await, Err(ResolutionFailure::Timeout)));
+    }
+}
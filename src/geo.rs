@@ -0,0 +1,101 @@
+use crate::record::HostRecord;
+use clap::ValueEnum;
+use maxminddb::{geoip2, Reader};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::net::IpAddr;
+
+/// ASN and location of an IP from the offline MMDB files
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct GeoInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub as_org: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+}
+
+/// MaxMind-format databases: an ASN database (GeoLite2-ASN) and a location
+/// database (GeoLite2-City or GeoLite2-Country)
+pub struct GeoDb {
+    asn: Option<Reader<Vec<u8>>>,
+    location: Option<Reader<Vec<u8>>>,
+}
+
+fn open_mmdb(path: &str) -> io::Result<Reader<Vec<u8>>> {
+    Reader::open_readfile(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+}
+
+impl GeoDb {
+    pub fn open(asn_path: Option<&str>, location_path: Option<&str>) -> io::Result<Self> {
+        Ok(GeoDb {
+            asn: asn_path.map(open_mmdb).transpose()?,
+            location: location_path.map(open_mmdb).transpose()?,
+        })
+    }
+
+    pub fn lookup(&self, ip: IpAddr) -> GeoInfo {
+        let mut info = GeoInfo::default();
+        if let Some(Ok(asn)) = self.asn.as_ref().map(|db| db.lookup::<geoip2::Asn>(ip)) {
+            info.asn = asn.autonomous_system_number;
+            info.as_org = asn.autonomous_system_organization.map(str::to_string);
+        }
+        // City records are a superset of country records, so this reads either database
+        if let Some(Ok(city)) = self.location.as_ref().map(|db| db.lookup::<geoip2::City>(ip)) {
+            info.country = city.country.and_then(|c| c.iso_code).map(str::to_string);
+            info.city = city
+                .city
+                .and_then(|c| c.names)
+                .and_then(|names| names.get("en").map(|name| name.to_string()));
+        }
+        info
+    }
+}
+
+/// Field to group the final summary by
+#[derive(Clone, Copy, ValueEnum)]
+pub enum GroupBy {
+    Asn,
+    Country,
+    City,
+}
+
+/// Print hosts, IPs and open ports per ASN, country or city
+pub fn print_groups(records: &HashMap<String, HostRecord>, by: GroupBy) {
+    // group -> (hosts, ips, ports)
+    let mut groups: BTreeMap<String, (BTreeSet<&str>, BTreeSet<&str>, usize)> = BTreeMap::new();
+    for record in records.values() {
+        for ip_record in &record.ips {
+            let geo = &ip_record.geo;
+            let key = match by {
+                GroupBy::Asn => geo.asn.map(|asn| match &geo.as_org {
+                    Some(org) => format!("AS{} ({})", asn, org),
+                    None => format!("AS{}", asn),
+                }),
+                GroupBy::Country => geo.country.clone(),
+                GroupBy::City => geo.city.as_ref().map(|city| match &geo.country {
+                    Some(country) => format!("{}, {}", city, country),
+                    None => city.clone(),
+                }),
+            };
+            let group = groups.entry(key.unwrap_or_else(|| "unknown".to_string())).or_default();
+            group.0.insert(&record.host);
+            group.1.insert(&ip_record.result.ip);
+            group.2 += ip_record.result.ports.len();
+        }
+    }
+
+    let title = match by {
+        GroupBy::Asn => "ASN",
+        GroupBy::Country => "country",
+        GroupBy::City => "city",
+    };
+    println!("\n--- Results by {} ---", title);
+    for (group, (hosts, ips, ports)) in &groups {
+        println!("{}: {} hosts, {} IPs, {} ports", group, hosts.len(), ips.len(), ports);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::record::HostRecord;
+use rusqlite::{params, Connection};
+use std::collections::HashMap;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Show when each port of a host first and last appeared
+#[derive(clap::Args)]
+pub struct HistoryArgs {
+    /// Host to show the timeline of
+    pub host: String,
+
+    /// History database written by scans run with --history-db
+    #[arg(long, value_name = "DB_FILE")]
+    pub db: String,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at INTEGER NOT NULL,
+    input TEXT NOT NULL
+);
+-- One row per host and run, holding the host's JSON record as written by --json
+CREATE TABLE IF NOT EXISTS hosts (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    host TEXT NOT NULL,
+    record TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS ports (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    host TEXT NOT NULL,
+    ip TEXT NOT NULL,
+    port INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS hosts_by_host ON hosts(host);
+CREATE INDEX IF NOT EXISTS ports_by_host ON ports(host);
+";
+
+fn db_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(format!("history database: {}", e))
+}
+
+fn open(path: &str) -> io::Result<Connection> {
+    let conn = Connection::open(path).map_err(db_error)?;
+    conn.execute_batch(SCHEMA).map_err(db_error)?;
+    Ok(conn)
+}
+
+/// Store one run's results in the history database, creating it if needed.
+/// Returns the run's id.
+pub fn record_run(path: &str, input: &str, started_at: SystemTime, records: &HashMap<String, HostRecord>) -> io::Result<i64> {
+    let mut conn = open(path)?;
+    let started_at = started_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+    let tx = conn.transaction().map_err(db_error)?;
+    tx.execute("INSERT INTO runs (started_at, input) VALUES (?1, ?2)", params![started_at, input])
+        .map_err(db_error)?;
+    let run_id = tx.last_insert_rowid();
+    {
+        let mut insert_host = tx.prepare("INSERT INTO hosts (run_id, host, record) VALUES (?1, ?2, ?3)").map_err(db_error)?;
+        let mut insert_port = tx
+            .prepare("INSERT INTO ports (run_id, host, ip, port) VALUES (?1, ?2, ?3, ?4)")
+            .map_err(db_error)?;
+        for record in records.values() {
+            insert_host
+                .execute(params![run_id, record.host, serde_json::to_string(record)?])
+                .map_err(db_error)?;
+            for ip_record in &record.ips {
+                for port in &ip_record.result.ports {
+                    insert_port
+                        .execute(params![run_id, record.host, ip_record.result.ip, port])
+                        .map_err(db_error)?;
+                }
+            }
+        }
+    }
+    tx.commit().map_err(db_error)?;
+    Ok(run_id)
+}
+
+/// Runs in which at least one IP of the host answered. Runs where resolution
+/// or every InternetDB query failed say nothing about its ports.
+const ANSWERED: &str = "EXISTS (SELECT 1 FROM json_each(h.record, '$.ips') WHERE json_extract(value, '$.error') IS NULL)";
+
+/// When one port of a host was seen, across all runs
+struct PortHistory {
+    port: u16,
+    first_seen: String,
+    last_seen: String,
+    runs: i64,
+    ips: String,
+    /// Reported by the latest run in which the host answered
+    open: bool,
+}
+
+/// The latest answered run of `host`, by run id so runs started in the same second stay apart
+fn last_answered_run(conn: &Connection, host: &str) -> rusqlite::Result<Option<i64>> {
+    conn.query_row(
+        &format!("SELECT MAX(h.run_id) FROM hosts h WHERE h.host = ?1 AND {}", ANSWERED),
+        params![host],
+        |row| row.get(0),
+    )
+}
+
+fn port_history(conn: &Connection, host: &str) -> rusqlite::Result<Vec<PortHistory>> {
+    let last_run = last_answered_run(conn, host)?;
+    let mut stmt = conn.prepare(
+        "SELECT p.port, datetime(MIN(r.started_at), 'unixepoch'), datetime(MAX(r.started_at), 'unixepoch'),
+                MAX(p.run_id), COUNT(DISTINCT r.id), GROUP_CONCAT(DISTINCT p.ip)
+         FROM ports p JOIN runs r ON r.id = p.run_id WHERE p.host = ?1
+         GROUP BY p.port ORDER BY p.port",
+    )?;
+    let rows = stmt.query_map(params![host], |row| {
+        Ok(PortHistory {
+            port: row.get(0)?,
+            first_seen: row.get(1)?,
+            last_seen: row.get(2)?,
+            open: Some(row.get::<_, i64>(3)?) == last_run,
+            runs: row.get(4)?,
+            ips: row.get(5)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn run(args: &HistoryArgs) -> io::Result<()> {
+    let host = crate::input::normalize_host(&args.host).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let conn = open(&args.db)?;
+
+    let (runs, first_run, last_run, last_run_id): (i64, Option<String>, Option<String>, Option<i64>) = conn
+        .query_row(
+            "SELECT COUNT(*), datetime(MIN(r.started_at), 'unixepoch'), datetime(MAX(r.started_at), 'unixepoch'), MAX(r.id)
+             FROM hosts h JOIN runs r ON r.id = h.run_id WHERE h.host = ?1",
+            params![host],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .map_err(db_error)?;
+    let (Some(first_run), Some(last_run)) = (first_run, last_run) else {
+        println!("{} is not in {}", host, args.db);
+        return Ok(());
+    };
+    println!("{}: scanned in {} runs, {} to {} (UTC)", host, runs, first_run, last_run);
+    match last_answered_run(&conn, &host).map_err(db_error)? {
+        None => println!("No run got an answer for {}", host),
+        Some(answered) if Some(answered) != last_run_id => {
+            let answered_at: String = conn
+                .query_row("SELECT datetime(started_at, 'unixepoch') FROM runs WHERE id = ?1", params![answered], |row| row.get(0))
+                .map_err(db_error)?;
+            println!("Later runs failed to query {}, status is as of {}", host, answered_at);
+        }
+        Some(_) => {}
+    }
+
+    println!("{:<7}{:<21}{:<21}{:<6}{:<8}IPS", "PORT", "FIRST SEEN", "LAST SEEN", "RUNS", "STATUS");
+    for row in port_history(&conn, &host).map_err(db_error)? {
+        let status = if row.open { "open" } else { "closed" };
+        println!(
+            "{:<7}{:<21}{:<21}{:<6}{:<8}{}",
+            row.port,
+            row.first_seen,
+            row.last_seen,
+            row.runs,
+            status,
+            row.ips.replace(',', ", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::scan_from_json as scan;
+    use serde_json::json;
+
+    #[test]
+    fn failed_runs_do_not_close_ports() {
+        let path = std::env::temp_dir().join(format!("qport-history-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = path.to_str().unwrap();
+        // All in the same second, so only the run ids tell them apart
+        let started_at = UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+
+        let record = |ips| record_run(db, "hosts.txt", started_at, &scan(&[("a.example.com", ips)])).unwrap();
+        record(json!([{ "ip": "192.0.2.1", "ports": [80, 443], "hostnames": [] }]));
+        record(json!([{ "ip": "192.0.2.1", "ports": [80], "hostnames": [] }]));
+        record(json!([{ "ip": "192.0.2.1", "ports": [], "hostnames": [], "error": "HTTP 503" }]));
+        record(json!([]));
+
+        let conn = open(db).unwrap();
+        assert_eq!(last_answered_run(&conn, "a.example.com").unwrap(), Some(2));
+        let ports = port_history(&conn, "a.example.com").unwrap();
+        let status: Vec<(u16, bool, i64)> = ports.iter().map(|p| (p.port, p.open, p.runs)).collect();
+        assert_eq!(status, vec![(80, true, 2), (443, false, 1)]);
+        assert_eq!(ports[0].first_seen, "2023-11-14 22:13:20");
+        assert_eq!(ports[0].ips, "192.0.2.1");
+        assert_eq!(last_answered_run(&conn, "b.example.com").unwrap(), None);
+
+        drop(conn);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,304 @@
+use crate::asn::{AsnDb, AsnSelector};
+use crate::pipeline::Target;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::IpAddr;
+
+/// What happened to the input lines on the way to becoming targets
+#[derive(Default)]
+pub struct InputReport {
+    /// Lines that held a host (blank and comment-only lines excluded)
+    pub lines: usize,
+    /// Hosts whose spelling changed (case, trailing dot, IDN, IP form)
+    pub normalized: usize,
+    /// Lines naming a host an earlier line already named
+    pub duplicates: usize,
+    /// Rejected lines with their line number and reason
+    pub invalid: Vec<(usize, String, String)>,
+    /// IPs added by expanding `AS64500` / `org:` lines
+    pub asn_expanded: usize,
+    /// IPv6 ranges of expanded ASes that were left out, being too large to enumerate
+    pub asn_ipv6_skipped: usize,
+}
+
+/// Read the input file into deduplicated targets. Lines for the same host
+/// are merged, keeping every port that was given. `AS64500` and
+/// `org:<name>` lines are expanded into IPs through `asn_db`, as long as the
+/// expansion stays within `max_asn_ips`. Only IPv4 ranges are expanded.
+pub fn load_targets(path: &str, asn_db: Option<&AsnDb>, max_asn_ips: u128) -> io::Result<(Vec<Target>, InputReport)> {
+    let mut targets: Vec<Target> = vec![];
+    let mut report = InputReport::default();
+    let mut index: HashMap<String, usize> = HashMap::new();
+
+    let reader = BufReader::new(File::open(path)?);
+    for (n, line) in reader.lines().enumerate() {
+        let line = line?;
+        if let Some(selector) = AsnSelector::parse(&line) {
+            report.lines += 1;
+            match expand_asn(&selector, asn_db, max_asn_ips) {
+                Ok((ips, ipv6_skipped)) => {
+                    report.asn_ipv6_skipped += ipv6_skipped;
+                    for ip in ips {
+                        report.asn_expanded += 1;
+                        add_target(&mut targets, &mut index, &mut report, ip.to_string(), None);
+                    }
+                }
+                Err(reason) => report.invalid.push((n + 1, line.trim().to_string(), reason)),
+            }
+            continue;
+        }
+
+        let Some((raw_host, port)) = parse_line(&line) else {
+            continue;
+        };
+        report.lines += 1;
+        let host = match normalize_host(&raw_host) {
+            Ok(host) => host,
+            Err(reason) => {
+                report.invalid.push((n + 1, line.trim().to_string(), reason));
+                continue;
+            }
+        };
+        if host != raw_host {
+            report.normalized += 1;
+        }
+        add_target(&mut targets, &mut index, &mut report, host, port);
+    }
+    Ok((targets, report))
+}
+
+fn add_target(targets: &mut Vec<Target>, index: &mut HashMap<String, usize>, report: &mut InputReport, host: String, port: Option<u16>) {
+    if let Some(&i) = index.get(&host) {
+        report.duplicates += 1;
+        match (&mut targets[i].input_ports, port) {
+            (Some(ports), Some(port)) if !ports.contains(&port) => ports.push(port),
+            (ports, None) => *ports = None,
+            _ => {}
+        }
+        return;
+    }
+    index.insert(host.clone(), targets.len());
+    targets.push(Target {
+        host,
+        depth: 0,
+        discovered_from: None,
+        input_ports: port.map(|p| vec![p]),
+    });
+}
+
+/// The IPv4 addresses of the selected ASes, and how many IPv6 ranges were
+/// skipped. A single IPv6 /64 is 2^64 addresses, so those are never expanded
+/// and don't count toward `max_asn_ips`.
+fn expand_asn(selector: &AsnSelector, asn_db: Option<&AsnDb>, max_asn_ips: u128) -> Result<(Vec<IpAddr>, usize), String> {
+    let Some(db) = asn_db else {
+        return Err("ASN lines need --asn-db".to_string());
+    };
+    let asns = db.select(selector);
+    if asns.is_empty() {
+        return Err("no matching AS in the ASN database".to_string());
+    }
+    let (ranges, ipv6_ranges): (Vec<_>, Vec<_>) =
+        asns.iter().flat_map(|asn| db.ranges(*asn)).partition(|range| range.start.is_ipv4());
+    if ranges.is_empty() {
+        return Err(format!("only IPv6 ranges ({}), which are too large to expand", ipv6_ranges.len()));
+    }
+    let total = ranges.iter().fold(0u128, |sum, range| sum.saturating_add(range.size()));
+    if total > max_asn_ips {
+        return Err(format!(
+            "expands to {} IPs across {} ranges, above --max-asn-ips {}",
+            total,
+            ranges.len(),
+            max_asn_ips
+        ));
+    }
+    Ok((ranges.iter().flat_map(|range| range.ips()).collect(), ipv6_ranges.len()))
+}
+
+/// Canonicalize a host: IPs in their standard form, names lowercased,
+/// without trailing dots and with IDNs converted to punycode.
+pub fn normalize_host(host: &str) -> Result<String, String> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Ok(ip.to_string());
+    }
+    let name = host.trim_end_matches('.');
+    let name = idna::domain_to_ascii(name).map_err(|_| "invalid internationalized name".to_string())?;
+    if name.is_empty() {
+        return Err("empty hostname".to_string());
+    }
+    if name.len() > 253 {
+        return Err("hostname longer than 253 characters".to_string());
+    }
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(format!("invalid label length in '{}'", name));
+        }
+        if !label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') {
+            return Err(format!("invalid characters in '{}'", name));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err(format!("label starts or ends with '-' in '{}'", name));
+        }
+    }
+    Ok(name)
+}
+
+/// Pull the host (and port, if one was given) out of an input line. Accepts
+/// bare hosts and IPs, `host:port`, `[ipv6]:port` and URLs such as
+/// `https://app.example.com:8443/path`. Returns `None` for blank and
+/// comment-only lines.
+pub fn parse_line(line: &str) -> Option<(String, Option<u16>)> {
+    let line = line.split('#').next().unwrap_or("").trim();
+    if line.is_empty() {
+        return None;
+    }
+
+    let (scheme, rest) = match line.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme.to_ascii_lowercase()), rest),
+        None => (None, line),
+    };
+    // Drop any path or query, then any userinfo
+    let authority = rest.split(['/', '?']).next().unwrap_or(rest);
+    let authority = authority.rsplit_once('@').map(|(_, a)| a).unwrap_or(authority);
+
+    let (host, port) = split_host_port(authority);
+    let port = port.or(match scheme.as_deref() {
+        Some("http") => Some(80),
+        Some("https") => Some(443),
+        _ => None,
+    });
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_string(), port))
+}
+
+fn split_host_port(authority: &str) -> (&str, Option<u16>) {
+    if let Some(rest) = authority.strip_prefix('[') {
+        if let Some((host, after)) = rest.split_once(']') {
+            let port = after.strip_prefix(':').and_then(|p| p.parse().ok());
+            return (host, port);
+        }
+    }
+    // A bare IPv6 address has colons but no port
+    if authority.parse::<IpAddr>().is_ok() {
+        return (authority, None);
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) if !host.contains(':') => match port.parse() {
+            Ok(port) => (host, Some(port)),
+            Err(_) => (authority, None),
+        },
+        _ => (authority, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(host: &str, port: Option<u16>) -> Option<(String, Option<u16>)> {
+        Some((host.to_string(), port))
+    }
+
+    #[test]
+    fn parse_line_urls() {
+        assert_eq!(parse_line("https://user@App.example.com:8443/p?q#f"), parsed("App.example.com", Some(8443)));
+        assert_eq!(parse_line("https://app.example.com/login"), parsed("app.example.com", Some(443)));
+        assert_eq!(parse_line("HTTP://app.example.com?x=1"), parsed("app.example.com", Some(80)));
+        assert_eq!(parse_line("ftp://files.example.com"), parsed("files.example.com", None));
+        assert_eq!(parse_line("https://[2001:db8::1]:8443/"), parsed("2001:db8::1", Some(8443)));
+    }
+
+    #[test]
+    fn parse_line_hosts_and_ports() {
+        assert_eq!(parse_line("[2001:db8::1]:443"), parsed("2001:db8::1", Some(443)));
+        assert_eq!(parse_line("[2001:db8::1]"), parsed("2001:db8::1", None));
+        assert_eq!(parse_line("2001:db8::1"), parsed("2001:db8::1", None));
+        assert_eq!(parse_line("::ffff:192.0.2.1"), parsed("::ffff:192.0.2.1", None));
+        assert_eq!(parse_line("  example.com:8080 # staging  "), parsed("example.com", Some(8080)));
+        assert_eq!(parse_line("192.0.2.1:22"), parsed("192.0.2.1", Some(22)));
+        assert_eq!(parse_line("example.com:http"), parsed("example.com:http", None));
+    }
+
+    #[test]
+    fn parse_line_skips_blank_and_comment_lines() {
+        assert_eq!(parse_line(""), None);
+        assert_eq!(parse_line("   "), None);
+        assert_eq!(parse_line("# just a comment"), None);
+        assert_eq!(parse_line("https://"), None);
+    }
+
+    #[test]
+    fn normalize_host_canonical_forms() {
+        assert_eq!(normalize_host("Example.COM.").as_deref(), Ok("example.com"));
+        assert_eq!(normalize_host("bücher.example").as_deref(), Ok("xn--bcher-kva.example"));
+        assert_eq!(normalize_host("_dmarc.example.com").as_deref(), Ok("_dmarc.example.com"));
+        assert_eq!(normalize_host("2001:DB8:0::1").as_deref(), Ok("2001:db8::1"));
+    }
+
+    #[test]
+    fn normalize_host_rejects_bad_names() {
+        assert!(normalize_host("bad_host!.com").unwrap_err().starts_with("invalid characters"));
+        let long_label = format!("{}.example.com", "a".repeat(64));
+        assert!(normalize_host(&long_label).unwrap_err().starts_with("invalid label length"));
+        assert!(normalize_host(&format!("{}.example.com", "a".repeat(63))).is_ok());
+        assert!(normalize_host("a..example.com").is_err());
+        assert!(normalize_host("-a.example.com").is_err());
+        let long_name = vec!["a".repeat(60); 5].join(".");
+        assert_eq!(normalize_host(&long_name).unwrap_err(), "hostname longer than 253 characters");
+    }
+
+    #[test]
+    fn load_targets_deduplicates_and_merges_ports() {
+        let path = std::env::temp_dir().join(format!("qport-input-{}.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "# targets\nExample.COM.\nexample.com:8443\nhttps://api.example.com\napi.example.com:8443\nbad_host!.com\n\n",
+        )
+        .unwrap();
+        let (targets, report) = load_targets(path.to_str().unwrap(), None, 0).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let hosts: Vec<&str> = targets.iter().map(|t| t.host.as_str()).collect();
+        assert_eq!(hosts, vec!["example.com", "api.example.com"]);
+        // A bare host line means every port, so the port list is dropped
+        assert_eq!(targets[0].input_ports, None);
+        assert_eq!(targets[1].input_ports, Some(vec![443, 8443]));
+        assert_eq!(report.lines, 5);
+        assert_eq!(report.normalized, 1);
+        assert_eq!(report.duplicates, 2);
+        assert_eq!(report.invalid.len(), 1);
+        assert_eq!(report.invalid[0].0, 6);
+    }
+
+    #[test]
+    fn asn_expansion_skips_ipv6_ranges() {
+        let dir = std::env::temp_dir();
+        let db_path = dir.join(format!("qport-asn-{}.tsv", std::process::id()));
+        std::fs::write(
+            &db_path,
+            "192.0.2.0\t192.0.2.3\t64500\tZZ\tEXAMPLE-NET\n\
+             2001:db8::\t2001:db8:ffff:ffff:ffff:ffff:ffff:ffff\t64500\tZZ\tEXAMPLE-NET\n\
+             2001:db9::\t2001:db9::ffff\t64501\tZZ\tV6-ONLY\n",
+        )
+        .unwrap();
+        let db = AsnDb::load(db_path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&db_path).unwrap();
+
+        let input_path = dir.join(format!("qport-asn-input-{}.txt", std::process::id()));
+        std::fs::write(&input_path, "AS64500\nAS64501\n").unwrap();
+        let (targets, report) = load_targets(input_path.to_str().unwrap(), Some(&db), 4).unwrap();
+        let (_, too_small) = load_targets(input_path.to_str().unwrap(), Some(&db), 3).unwrap();
+        std::fs::remove_file(&input_path).unwrap();
+
+        let hosts: Vec<&str> = targets.iter().map(|t| t.host.as_str()).collect();
+        assert_eq!(hosts, vec!["192.0.2.0", "192.0.2.1", "192.0.2.2", "192.0.2.3"]);
+        assert_eq!(report.asn_expanded, 4);
+        assert_eq!(report.asn_ipv6_skipped, 1);
+        assert_eq!(report.invalid.len(), 1);
+        assert!(report.invalid[0].2.starts_with("only IPv6 ranges"));
+        // The guardrail counts IPv4 addresses only
+        assert!(too_small.invalid[0].2.starts_with("expands to 4 IPs"));
+    }
+}
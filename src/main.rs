@@ -1,19 +1,57 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use reqwest::Client;
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, Write};
+use hickory_resolver::config::LookupIpStrategy;
+use std::sync::Arc;
 use std::time::Duration;
 use rand::Rng;
 
+mod asn;
+mod bogon;
+mod cdn;
+mod cpe;
+mod diff;
+mod dns;
+mod geo;
+mod history;
+mod input;
+mod pipeline;
+mod probe;
+mod record;
+mod report;
+mod scope;
+mod services;
+mod vuln;
+mod watch;
+mod webhook;
+mod wildcard;
+
+use pipeline::Target;
+use record::{Discovery, HostRecord, IpRecord};
+
 #[derive(Parser)]
 #[command(name = "qport")]
 #[command(about = "Fast passive port scanner using Shodan InternetDB")]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+/// Scan options, shared by a plain run and `qport watch`
+#[derive(clap::Args, Clone)]
+#[command(group = clap::ArgGroup::new("family").multiple(false))]
 struct Args {
     /// Input file with list of hosts (one per line)
-    #[arg(short, long)]
-    input: String,
+    #[arg(short, long, required = true)]
+    input: Option<String>,
 
     /// Output file for results (optional, auto-generated if not provided)
     #[arg(short, long)]
@@ -34,13 +72,206 @@ struct Args {
     /// Generate unique output file excluding common ports 80,443
     #[arg(short = 'u', long, value_name = "UNIQUE_FILE")]
     uniq: Option<String>,
+
+    /// iptoasn-format TSV used to expand AS64500 and org:<name> input lines into IPs
+    #[arg(long, value_name = "ASN_TSV")]
+    asn_db: Option<String>,
+
+    /// Refuse ASN expansions larger than this many IPv4 addresses
+    #[arg(long, value_name = "N", default_value_t = 65536)]
+    max_asn_ips: u128,
+
+    /// Write rejected input lines with the reason to this file
+    #[arg(long, value_name = "REJECTS_FILE")]
+    rejects: Option<String>,
+
+    /// Save the host -> IP -> ports -> hostnames mapping as JSON lines
+    #[arg(short = 'j', long, value_name = "JSON_FILE")]
+    json: Option<String>,
+
+    /// Scope file of allowed domains (*.domain for subdomains), CIDRs and IPs
+    #[arg(long, value_name = "SCOPE_FILE")]
+    scope: Option<String>,
+
+    /// Exclude file of domains, CIDRs and IPs that must never be queried
+    #[arg(long, value_name = "EXCLUDE_FILE")]
+    exclude: Option<String>,
+
+    /// Query private, reserved and bogon addresses instead of skipping them
+    #[arg(long)]
+    allow_private: bool,
+
+    /// File with nameservers to resolve hosts with instead of the system ones
+    #[arg(long, value_name = "RESOLVERS_FILE")]
+    resolvers: Option<String>,
+
+    /// How queries are spread across the nameservers in the resolvers file
+    #[arg(long, value_enum, default_value_t = dns::ResolverMode::RoundRobin)]
+    resolver_mode: dns::ResolverMode,
+
+    /// Per-query DNS timeout in seconds
+    #[arg(long, value_name = "SECS", default_value_t = 5)]
+    dns_timeout: u64,
+
+    /// Only resolve and query IPv4 addresses
+    #[arg(long, group = "family")]
+    ipv4_only: bool,
+
+    /// Only resolve and query IPv6 addresses
+    #[arg(long, group = "family")]
+    ipv6_only: bool,
+
+    /// Resolve and query both IPv4 and IPv6 addresses (default)
+    #[arg(long, group = "family")]
+    both: bool,
+
+    /// Skip IPs fronted by a known CDN/WAF instead of only flagging them
+    #[arg(long)]
+    skip_cdn: bool,
+
+    /// Detect wildcard DNS per parent zone; the first host resolving only to it is queried, the rest are marked or dropped
+    #[arg(long, value_enum, value_name = "MODE")]
+    wildcards: Option<wildcard::WildcardMode>,
+
+    /// Number of hosts resolved concurrently ahead of the InternetDB queries
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    dns_concurrency: usize,
+
+    /// Look up PTR names for every queried IP
+    #[arg(long)]
+    ptr: bool,
+
+    /// For input lines that carry a port (host:port or a URL), only keep results for that port
+    #[arg(long)]
+    match_input_port: bool,
+
+    /// MaxMind-format ASN database (e.g. GeoLite2-ASN.mmdb) to tag each IP with its AS
+    #[arg(long, value_name = "MMDB")]
+    asn_mmdb: Option<String>,
+
+    /// MaxMind-format City or Country database to tag each IP with its location
+    #[arg(long, value_name = "MMDB")]
+    geo_mmdb: Option<String>,
+
+    /// Only query IPs in this country, by ISO code (repeatable, needs --geo-mmdb)
+    #[arg(long, value_name = "CC", requires = "geo_mmdb")]
+    country: Vec<String>,
+
+    /// Only query IPs announced by this AS number (repeatable, needs --asn-mmdb)
+    #[arg(long, value_name = "ASN", requires = "asn_mmdb")]
+    asn: Vec<u32>,
+
+    /// Print a summary of hosts, IPs and ports grouped by AS, country or city (not with --silent)
+    #[arg(long, value_enum, value_name = "FIELD")]
+    group_by: Option<geo::GroupBy>,
+
+    /// Offline CVE details: a JSON array of {id, cvss, title, kev} or the CISA KEV catalog (repeatable)
+    #[arg(long, value_name = "CVE_JSON")]
+    cve_db: Vec<String>,
+
+    /// Only keep IPs with a CVE scoring at least this CVSS (needs --cve-db)
+    #[arg(long, value_name = "SCORE", requires = "cve_db")]
+    min_cvss: Option<f32>,
+
+    /// Only keep IPs affected by this CVE (repeatable)
+    #[arg(long, value_name = "CVE")]
+    cve: Vec<String>,
+
+    /// Write the affected host:port pairs per CVE to this file
+    #[arg(long, value_name = "REPORT_FILE")]
+    vuln_report: Option<String>,
+
+    /// Only keep IPs InternetDB tagged with this tag, e.g. cloud or database (repeatable)
+    #[arg(long, value_name = "TAG")]
+    tag: Vec<String>,
+
+    /// Drop IPs InternetDB tagged with this tag, e.g. cdn or honeypot (repeatable)
+    #[arg(long, value_name = "TAG")]
+    exclude_tag: Vec<String>,
+
+    /// Only keep IPs running this product, optionally vendor-qualified and versioned,
+    /// e.g. nginx, openssh<8 or apache:http_server>=2.4.49 (repeatable)
+    #[arg(long, value_name = "PRODUCT", value_parser = cpe::ProductFilter::parse)]
+    product: Vec<cpe::ProductFilter>,
+
+    /// Write one host,ip,vendor,product,version row per detected product to this file
+    #[arg(long, value_name = "CSV_FILE")]
+    products_csv: Option<String>,
+
+    /// Name the service behind each port, e.g. host:22/ssh, from the bundled port table
+    #[arg(long)]
+    services: bool,
+
+    /// Extra or overriding port to service names, one "port name" per line (implies --services)
+    #[arg(long, value_name = "SERVICES_FILE")]
+    services_file: Option<String>,
+
+    /// Compare the results with an earlier --json scan and print what changed
+    #[arg(long, value_name = "JSON_FILE")]
+    baseline: Option<String>,
+
+    /// Record this run's results in a SQLite history database (see `qport history`)
+    #[arg(long, value_name = "DB_FILE")]
+    history_db: Option<String>,
+
+    /// POST new ports, CVEs and other changes (against --baseline, if given) to this URL
+    #[arg(long, value_name = "URL")]
+    webhook: Option<String>,
+
+    /// JSON template for the webhook body, with "{{events}}", {{count}} and {{text}} placeholders
+    #[arg(long, value_name = "TEMPLATE_FILE", requires = "webhook")]
+    webhook_template: Option<String>,
+
+    /// Retries per webhook batch on connection errors, 429 and 5xx
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    webhook_retries: u32,
+
+    /// Maximum number of events per webhook POST
+    #[arg(long, value_name = "N", default_value_t = 50)]
+    webhook_batch: usize,
+
+    /// Write a self-contained HTML report (summary, port chart, tags, CVEs, hosts) to this file
+    #[arg(long, value_name = "HTML_FILE")]
+    html: Option<String>,
+
+    /// Queue hostnames InternetDB reports for each IP as new targets
+    #[arg(long)]
+    recurse: bool,
+
+    /// Maximum discovery depth when recursing
+    #[arg(long, value_name = "DEPTH", default_value_t = 1)]
+    max_depth: usize,
+
+    /// Only recurse into names under this domain (repeatable, defaults to the --scope domains or the input hosts)
+    #[arg(long, value_name = "DOMAIN")]
+    recurse_scope: Vec<String>,
+
+    /// Probe web ports over HTTP(S) and save status, title and server to this file
+    #[arg(long, value_name = "PROBE_FILE")]
+    http_probe: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compare two scans saved with --json: new/closed ports, new/vanished hosts, new CVEs and tags
+    Diff(diff::DiffArgs),
+    /// Show when each port of a host first and last appeared, from a --history-db database
+    History(history::HistoryArgs),
+    /// Rescan on an interval and emit only what changed since the previous run
+    Watch(Box<watch::WatchArgs>),
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, Default)]
 struct ShodanResult {
     ip: String,
     ports: Vec<u16>,
     hostnames: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    vulns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    cpes: Vec<String>,
 }
 
 fn get_user_agents() -> Vec<&'static str> {
@@ -226,24 +457,37 @@ fn configure_system(verbose: bool) -> io::Result<()> {
     Ok(())
 }
 
-async fn resolve_host(host: &str) -> Vec<String> {
-    let mut ips = vec![];
-    match tokio::net::lookup_host((host, 0)).await {
-        Ok(addrs) => {
-            for addr in addrs {
-                ips.push(addr.ip().to_string());
+/// Format `host:port`, bracketing IPv6 literals (`[2001:db8::1]:443`)
+fn format_host_port(host: &str, port: u16) -> String {
+    if host.parse::<std::net::Ipv6Addr>().is_ok() {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Number of result IPs carrying each InternetDB tag, most common first
+fn tag_summary(records: &HashMap<String, HostRecord>) -> Vec<(String, usize)> {
+    let mut ips_by_tag: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for record in records.values() {
+        for ip_record in &record.ips {
+            for tag in &ip_record.result.tags {
+                ips_by_tag.entry(tag).or_default().insert(&ip_record.result.ip);
             }
         }
-        Err(_) => {
-            // If not resolvable, assume it's an IP
-            ips.push(host.to_string());
-        }
     }
-    ips
+    let mut counts: Vec<(String, usize)> = ips_by_tag.into_iter().map(|(tag, ips)| (tag.to_string(), ips.len())).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// InternetDB takes both IPv4 and IPv6 addresses unbracketed in the path
+fn internetdb_url(ip: &str) -> String {
+    format!("https://internetdb.shodan.io/{}", ip)
 }
 
 async fn query_shodan(client: &Client, ip: &str, user_agent: &str, verbose: bool) -> Result<ShodanResult, Box<dyn std::error::Error + Send + Sync>> {
-    let url = format!("https://internetdb.shodan.io/{}", ip);
+    let url = internetdb_url(ip);
     let mut retries = 0;
     const MAX_RETRIES: u32 = 2;
     
@@ -280,30 +524,123 @@ async fn query_shodan(client: &Client, ip: &str, user_agent: &str, verbose: bool
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    match &cli.command {
+        Some(Command::Diff(diff_args)) => diff::run(diff_args),
+        Some(Command::History(history_args)) => history::run(history_args),
+        Some(Command::Watch(watch_args)) => watch::run(watch_args).await,
+        None => scan(&cli.args).await.map(|_| ()),
+    }
+}
+
+/// Run one scan of `args.input`, write every requested output and return the results
+async fn scan(args: &Args) -> io::Result<HashMap<String, HostRecord>> {
     let start_time = std::time::Instant::now();
+    let started_at = std::time::SystemTime::now();
+    // Required unless a subcommand was given
+    let input = args.input.clone().unwrap_or_default();
+    // Load the baseline and webhook template up front so mistakes fail before the scan
+    let baseline = args.baseline.as_deref().map(diff::load_scan).transpose()?;
+    let webhook = args
+        .webhook
+        .as_deref()
+        .map(|url| webhook::WebhookSink::new(url, args.webhook_template.as_deref(), args.webhook_retries, args.webhook_batch))
+        .transpose()?;
 
     // Auto-generate output filename if not provided
-    let output_filename = args.output.unwrap_or_else(|| {
-        if args.input.ends_with(".txt") {
-            args.input.replace(".txt", "_results.txt")
+    let output_filename = args.output.clone().unwrap_or_else(|| {
+        if input.ends_with(".txt") {
+            input.replace(".txt", "_results.txt")
         } else {
-            format!("{}_results.txt", args.input)
+            format!("{}_results.txt", input)
         }
     });
 
-    let num_hosts = {
-        let file = File::open(&args.input)?;
-        let reader = BufReader::new(file);
-        reader.lines().count()
-    };
-
-    println!("Input has {} hosts", num_hosts);
+    let asn_db = args.asn_db.as_deref().map(asn::AsnDb::load).transpose()?;
+    let (input_targets, input_report) = input::load_targets(&input, asn_db.as_ref(), args.max_asn_ips)?;
+    if !args.silent {
+        println!(
+            "Input has {} hosts ({} lines, {} normalized, {} duplicates, {} invalid)",
+            input_targets.len(),
+            input_report.lines,
+            input_report.normalized,
+            input_report.duplicates,
+            input_report.invalid.len()
+        );
+        if input_report.asn_expanded > 0 {
+            println!("Expanded ASN lines into {} IPs", input_report.asn_expanded);
+        }
+        if input_report.asn_ipv6_skipped > 0 {
+            println!("Skipped {} IPv6 ranges of ASN lines, too large to expand", input_report.asn_ipv6_skipped);
+        }
+    }
+    if let Some(rejects_filename) = &args.rejects {
+        let mut rejects_file = File::create(rejects_filename)?;
+        for (_, line, reason) in &input_report.invalid {
+            writeln!(rejects_file, "{}\t{}", line, reason)?;
+        }
+    }
+    if args.verbose {
+        for (n, line, reason) in &input_report.invalid {
+            eprintln!("- Rejected line {} '{}': {}", n, line, reason);
+        }
+    }
 
     if args.debug {
         println!("Debug mode enabled - showing detailed statistics");
     }
 
+    let scope = scope::Scope {
+        allow: args.scope.as_deref().map(scope::RuleSet::load).transpose()?,
+        deny: args.exclude.as_deref().map(scope::RuleSet::load).transpose()?,
+    };
+
+    let geo_db = if args.asn_mmdb.is_some() || args.geo_mmdb.is_some() {
+        Some(geo::GeoDb::open(args.asn_mmdb.as_deref(), args.geo_mmdb.as_deref())?)
+    } else {
+        None
+    };
+    let mut cve_db = vuln::CveDb::default();
+    for path in &args.cve_db {
+        cve_db.load(path)?;
+    }
+    if args.debug && !args.cve_db.is_empty() {
+        println!("Loaded {} CVEs from the CVE database", cve_db.len());
+    }
+    let vuln_filter = vuln::VulnFilter {
+        cves: args.cve.clone(),
+        min_cvss: args.min_cvss,
+    };
+    let services = if args.services || args.services_file.is_some() {
+        let mut table = services::ServiceTable::bundled();
+        if let Some(path) = &args.services_file {
+            let count = table.load_overrides(path)?;
+            if args.debug {
+                println!("Loaded {} service names from {}", count, path);
+            }
+        }
+        Some(table)
+    } else {
+        None
+    };
+    let countries: Vec<String> = args.country.iter().map(|c| c.to_uppercase()).collect();
+
+    let ip_strategy = if args.ipv4_only {
+        LookupIpStrategy::Ipv4Only
+    } else if args.ipv6_only {
+        LookupIpStrategy::Ipv6Only
+    } else {
+        LookupIpStrategy::Ipv4AndIpv6
+    };
+    let dns_timeout = Duration::from_secs(args.dns_timeout);
+    let resolver = Arc::new(match &args.resolvers {
+        Some(path) => dns::DnsResolver::from_file(path, args.resolver_mode, dns_timeout, ip_strategy)?,
+        None => dns::DnsResolver::system(dns_timeout, ip_strategy)?,
+    });
+    if args.debug && args.resolvers.is_some() {
+        println!("Using {} custom nameservers", resolver.server_count());
+    }
+
     configure_system(args.verbose)?;
 
     // Create HTTP client without default user agent since we'll rotate them
@@ -315,35 +652,165 @@ async fn main() -> io::Result<()> {
     // Get the list of user agents for rotation
     let user_agents = get_user_agents();
 
-    let file = File::open(&args.input)?;
-    let reader = BufReader::new(file);
+    // Resolution and wildcard detection run concurrently ahead of the query loop below
+    let wildcard_detector = args.wildcards.map(|_| Arc::new(wildcard::WildcardDetector::default()));
+    let (target_tx, mut resolved_rx) = pipeline::spawn_resolver(
+        resolver.clone(),
+        ip_strategy,
+        wildcard_detector,
+        args.dns_concurrency,
+        args.dns_concurrency * 4,
+    );
+    let mut in_flight = 0;
+    let mut out_of_scope = 0;
+
+    let input_hosts: Vec<String> = input_targets.iter().map(|t| t.host.clone()).collect();
+    let mut seen_hosts: HashSet<String> = input_hosts.iter().cloned().collect();
+
+    for target in input_targets {
+        if let Err(reason) = scope.check_host(&target.host) {
+            out_of_scope += 1;
+            eprintln!("- Skipping {}: {}", target.host, reason);
+            continue;
+        }
+        if target_tx.send(target).is_ok() {
+            in_flight += 1;
+        }
+    }
+
+    // Without --recurse-scope, recurse within the --scope domains, or else
+    // into subdomains of the input names
+    let recurse_scope: Vec<String> = if !args.recurse_scope.is_empty() {
+        args.recurse_scope.iter().map(|d| d.to_lowercase()).collect()
+    } else {
+        match scope.allow.as_ref().map(scope::RuleSet::domain_rules) {
+            Some(rules) if !rules.is_empty() => rules,
+            _ => input_hosts.into_iter().filter(|h| h.parse::<IpAddr>().is_err()).collect(),
+        }
+    };
+    if args.recurse && recurse_scope.is_empty() {
+        eprintln!("Warning: --recurse has no domain to stay within (the input is only IPs); pass --recurse-scope or a --scope file with domains");
+    }
 
     let mut processed_hosts = 0;
+    let mut discovered_hosts = 0;
+    let mut skipped_bogons = 0;
+    let mut cdn_ips = 0;
+    let mut filtered_geo = 0;
+    let mut filtered_vulns = 0;
+    let mut filtered_tags = 0;
+    let mut filtered_products = 0;
+    let mut honeypots: Vec<String> = vec![];
+    let mut wildcard_zones: HashSet<String> = HashSet::new();
+    let mut collapsed_wildcards = 0;
+    let mut resolution_failures: HashMap<dns::ResolutionFailure, usize> = HashMap::new();
     let mut successful_queries = 0;
     let mut failed_queries = 0;
     let mut all_results = HashMap::new();
     let mut request_count = 0; // Counter for user agent rotation
 
-    // Sequential processing like portmap - no concurrency
-    for line in reader.lines() {
-        let line = line?;
-        let host = line.trim().to_string();
-        if host.is_empty() {
-            continue;
-        }
+    // Sequential querying like portmap - only DNS resolution runs concurrently
+    while in_flight > 0 {
+        let Some(resolved) = resolved_rx.recv().await else {
+            break;
+        };
+        in_flight -= 1;
+        let target = resolved.target;
+        let host = target.host;
         processed_hosts += 1;
 
         if args.debug {
-            println!("Processing host {} ({}/{})", host, processed_hosts, num_hosts);
+            println!("Processing host {} ({}/{})", host, processed_hosts, processed_hosts + in_flight);
+        }
+
+        let mut host_record = HostRecord::new(&host);
+        host_record.discovered_from = target.discovered_from;
+        let resolution = match resolved.result {
+            Ok(resolution) => resolution,
+            Err(failure) => {
+                *resolution_failures.entry(failure).or_default() += 1;
+                if args.verbose {
+                    eprintln!("✗ Could not resolve {}: {}", host, failure);
+                }
+                host_record.resolution_error = Some(failure);
+                all_results.insert(host.clone(), host_record);
+                continue;
+            }
+        };
+        if args.debug && !resolution.cnames.is_empty() {
+            println!("  CNAME chain: {} -> {}", host, resolution.cnames.join(" -> "));
+        }
+        let cname_cdn = cdn::match_cnames(&resolution.cnames);
+        host_record.cnames = resolution.cnames;
+
+        // Wildcard answers would only repeat the same InternetDB lookups, so
+        // the first host of each wildcard zone is queried for all of them
+        if let Some(mode) = args.wildcards {
+            if let Some(zone) = resolved.wildcard {
+                if wildcard_zones.insert(zone.clone()) {
+                    if args.verbose {
+                        println!("- Querying {} for the *.{} wildcard", host, zone);
+                    }
+                    host_record.wildcard = Some(zone);
+                } else {
+                    collapsed_wildcards += 1;
+                    if args.verbose {
+                        println!("- Collapsing {}: resolves only to the *.{} wildcard", host, zone);
+                    }
+                    if let wildcard::WildcardMode::Mark = mode {
+                        host_record.wildcard = Some(zone);
+                        all_results.insert(host.clone(), host_record);
+                    }
+                    continue;
+                }
+            }
         }
 
-        // Resolve host to IPs
-        let ips = resolve_host(&host).await;
-        let mut host_results = vec![];
         let mut host_success_count = 0;
         let mut host_fail_count = 0;
 
-        for ip in ips {
+        for addr in resolution.ips {
+            let ip = addr.to_string();
+            if let Err(reason) = scope.check_ip(addr) {
+                out_of_scope += 1;
+                eprintln!("- Skipping {} ({}): {}", ip, host, reason);
+                continue;
+            }
+
+            // InternetDB has nothing for private and reserved ranges
+            if !args.allow_private {
+                if let Some(class) = bogon::classify(addr) {
+                    skipped_bogons += 1;
+                    if args.verbose {
+                        println!("- Skipping {} ({}): {} address", ip, host, class);
+                    }
+                    continue;
+                }
+            }
+
+            // CDN edges report the CDN's ports, not the origin's
+            let cdn_provider = cdn::match_ip(addr).or(cname_cdn);
+            if let Some(provider) = cdn_provider {
+                cdn_ips += 1;
+                if args.skip_cdn {
+                    if args.verbose {
+                        println!("- Skipping {} ({}): fronted by {}", ip, host, provider);
+                    }
+                    continue;
+                }
+            }
+
+            let geo_info = geo_db.as_ref().map(|db| db.lookup(addr)).unwrap_or_default();
+            let country_ok = countries.is_empty() || geo_info.country.as_ref().is_some_and(|c| countries.contains(c));
+            let asn_ok = args.asn.is_empty() || geo_info.asn.is_some_and(|asn| args.asn.contains(&asn));
+            if !country_ok || !asn_ok {
+                filtered_geo += 1;
+                if args.verbose {
+                    println!("- Skipping {} ({}): outside the --country/--asn filter", ip, host);
+                }
+                continue;
+            }
+
             // Ultra fast delay for ~500 requests per second (2ms average)
             let jitter = rand::thread_rng().gen_range(1..3);
             tokio::time::sleep(Duration::from_millis(jitter)).await;
@@ -352,25 +819,134 @@ async fn main() -> io::Result<()> {
             let user_agent = user_agents[request_count % user_agents.len()];
             request_count += 1;
             
-            match query_shodan(&client, &ip, user_agent, args.verbose).await {
-                Ok(result) => {
-                    let ports_count = result.ports.len();
-                    host_results.push((ip.clone(), result.ports));
+            // PTR lookups overlap with the InternetDB query
+            let ptr_lookup = async {
+                if args.ptr {
+                    resolver.reverse(addr).await
+                } else {
+                    vec![]
+                }
+            };
+            let (query_result, ptr_names) = tokio::join!(query_shodan(&client, &ip, user_agent, args.verbose), ptr_lookup);
+            if args.debug && !ptr_names.is_empty() {
+                println!("  PTR: {}", ptr_names.join(", "));
+            }
+
+            let mut ip_record = match query_result {
+                Ok(mut result) => {
+                    if args.match_input_port {
+                        if let Some(ports) = &target.input_ports {
+                            result.ports.retain(|p| ports.contains(p));
+                        }
+                    }
                     host_success_count += 1;
                     if args.debug {
-                        println!("✓ {}: {} ports found", ip, ports_count);
+                        match cdn_provider {
+                            Some(provider) => println!("✓ {}: {} ports found (CDN: {})", ip, result.ports.len(), provider),
+                            None => println!("✓ {}: {} ports found", ip, result.ports.len()),
+                        }
+                        if !result.hostnames.is_empty() {
+                            println!("  InternetDB hostnames: {}", result.hostnames.join(", "));
+                        }
+                        if !result.vulns.is_empty() {
+                            println!("  CVEs: {}", result.vulns.join(", "));
+                        }
+                        if !result.tags.is_empty() {
+                            println!("  Tags: {}", result.tags.join(", "));
+                        }
+                        if !result.cpes.is_empty() {
+                            println!("  CPEs: {}", result.cpes.join(", "));
+                        }
+                    }
+                    if args.recurse && target.depth < args.max_depth {
+                        for name in &result.hostnames {
+                            let name = name.trim_end_matches('.').to_lowercase();
+                            if !recurse_scope.iter().any(|d| scope::matches_domain(&name, d)) {
+                                if args.debug {
+                                    println!("  skipping out-of-scope hostname {}", name);
+                                }
+                                continue;
+                            }
+                            if seen_hosts.insert(name.clone()) {
+                                discovered_hosts += 1;
+                                if args.verbose {
+                                    println!("+ Discovered {} via {} ({})", name, ip, host);
+                                }
+                                if let Err(reason) = scope.check_host(&name) {
+                                    out_of_scope += 1;
+                                    eprintln!("- Skipping {}: {}", name, reason);
+                                    continue;
+                                }
+                                let discovered = Target {
+                                    host: name,
+                                    depth: target.depth + 1,
+                                    discovered_from: Some(Discovery {
+                                        host: host.clone(),
+                                        ip: ip.clone(),
+                                        depth: target.depth + 1,
+                                    }),
+                                    input_ports: None,
+                                };
+                                if target_tx.send(discovered).is_ok() {
+                                    in_flight += 1;
+                                }
+                            }
+                        }
                     }
+                    IpRecord::found(result)
                 }
                 Err(e) => {
                     host_fail_count += 1;
                     if args.verbose {
                         eprintln!("✗ Error querying {}: {}", ip, e);
                     }
+                    IpRecord::failed(&ip, e.to_string())
                 }
+            };
+            ip_record.cdn = cdn_provider.map(str::to_string);
+            ip_record.ptr = ptr_names;
+            ip_record.geo = geo_info;
+            if let Some(table) = &services {
+                ip_record.services = ip_record
+                    .result
+                    .ports
+                    .iter()
+                    .filter_map(|port| table.name(*port).map(|name| (*port, name.to_string())))
+                    .collect();
             }
+            if vuln_filter.is_active() && !ip_record.result.vulns.iter().any(|cve| vuln_filter.matches(cve, &cve_db)) {
+                filtered_vulns += 1;
+                if args.verbose {
+                    println!("- Dropping {} ({}): no CVE matching --cve/--min-cvss", ip, host);
+                }
+                continue;
+            }
+            let tags = &ip_record.result.tags;
+            let has_tag = |wanted: &String| tags.iter().any(|t| t.eq_ignore_ascii_case(wanted));
+            if (!args.tag.is_empty() && !args.tag.iter().any(has_tag)) || args.exclude_tag.iter().any(has_tag) {
+                filtered_tags += 1;
+                if args.verbose {
+                    println!("- Dropping {} ({}): tags [{}] outside --tag/--exclude-tag", ip, host, tags.join(", "));
+                }
+                continue;
+            }
+            if !args.product.is_empty() && !ip_record.products.iter().any(|p| args.product.iter().any(|f| f.matches(p))) {
+                filtered_products += 1;
+                if args.verbose {
+                    println!("- Dropping {} ({}): no product matching --product", ip, host);
+                }
+                continue;
+            }
+            // Honeypots answer on every port, so their results can't be trusted
+            if tags.iter().any(|t| t.eq_ignore_ascii_case("honeypot")) {
+                ip_record.honeypot = true;
+                eprintln!("! {} ({}) is tagged as a honeypot, its ports are likely fake", ip, host);
+                honeypots.push(format!("{} ({})", ip, host));
+            }
+            host_record.ips.push(ip_record);
         }
 
-        all_results.insert(host.clone(), host_results);
+        all_results.insert(host.clone(), host_record);
         successful_queries += host_success_count;
         failed_queries += host_fail_count;
 
@@ -379,6 +955,8 @@ async fn main() -> io::Result<()> {
         }
     }
 
+    let shared_backends = record::link_shared_backends(&mut all_results);
+
     let mut total_ports = 0;
     let mut unique_ports = 0;
     let mut output_file = File::create(&output_filename)?;
@@ -388,10 +966,13 @@ async fn main() -> io::Result<()> {
         None
     };
 
-    for (host, ip_ports) in &all_results {
-        for (_ip, ports) in ip_ports {
-            for port in ports {
-                let result_line = format!("{}:{}", host, port);
+    for (host, record) in &all_results {
+        for ip_record in &record.ips {
+            for port in &ip_record.result.ports {
+                let result_line = match ip_record.services.get(port) {
+                    Some(service) => format!("{}/{}", format_host_port(host, *port), service),
+                    None => format_host_port(host, *port),
+                };
                 writeln!(output_file, "{}", result_line)?;
                 if !args.silent {
                     println!("{}", result_line);
@@ -409,32 +990,226 @@ async fn main() -> io::Result<()> {
         }
     }
 
+    if let Some(json_filename) = &args.json {
+        let mut json_file = File::create(json_filename)?;
+        for record in all_results.values() {
+            writeln!(json_file, "{}", serde_json::to_string(record)?)?;
+        }
+    }
+
+    let mut reported_cves = 0;
+    if let Some(report_filename) = &args.vuln_report {
+        reported_cves = vuln::write_report(report_filename, &all_results, &cve_db, &vuln_filter)?;
+    }
+
+    let mut product_rows = 0;
+    if let Some(csv_filename) = &args.products_csv {
+        product_rows = cpe::write_csv(csv_filename, &all_results)?;
+    }
+
+    let mut probes_sent = 0;
+    let mut probes_answered = 0;
+    if let Some(probe_filename) = &args.http_probe {
+        let mut probe_file = File::create(probe_filename)?;
+        for (host, record) in &all_results {
+            for ip_record in &record.ips {
+                let result = &ip_record.result;
+                let web_ports: Vec<u16> = result.ports.iter().copied().filter(|p| probe::is_web_port(*p)).collect();
+                if web_ports.is_empty() {
+                    continue;
+                }
+                // Pin the hostname to the IP InternetDB reported these ports for
+                let probe_client = match probe::build_probe_client(host, &result.ip) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        if args.verbose {
+                            eprintln!("✗ Failed to build probe client for {}: {}", host, e);
+                        }
+                        continue;
+                    }
+                };
+                for port in web_ports {
+                    let user_agent = user_agents[request_count % user_agents.len()];
+                    request_count += 1;
+                    probes_sent += 1;
+
+                    match probe::probe(&probe_client, host, port, user_agent).await {
+                        Ok(probe_result) => {
+                            probes_answered += 1;
+                            let probe_line = probe_result.to_line();
+                            writeln!(probe_file, "{}", probe_line)?;
+                            if !args.silent {
+                                println!("{}", probe_line);
+                            }
+                        }
+                        Err(e) => {
+                            if args.verbose {
+                                eprintln!("✗ {}", e);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     let elapsed = start_time.elapsed();
 
+    if let Some(html_filename) = &args.html {
+        report::write_html(html_filename, &input, &all_results, &cve_db, elapsed)?;
+    }
+
     if args.debug || args.verbose {
         println!("\n--- Debug Statistics ---");
         println!("Total hosts processed: {}", processed_hosts);
+        if scope.is_active() {
+            println!("Skipped out of scope: {}", out_of_scope);
+        }
+        println!("Skipped private/reserved IPs: {}", skipped_bogons);
+        if args.skip_cdn {
+            println!("Skipped CDN-fronted IPs: {}", cdn_ips);
+        } else {
+            println!("CDN-fronted IPs: {}", cdn_ips);
+        }
+        if args.wildcards.is_some() {
+            println!("Wildcard entries collapsed: {}", collapsed_wildcards);
+        }
+        if !countries.is_empty() || !args.asn.is_empty() {
+            println!("Skipped by country/ASN filter: {}", filtered_geo);
+        }
+        if vuln_filter.is_active() {
+            println!("Dropped by CVE filter: {}", filtered_vulns);
+        }
+        if !args.tag.is_empty() || !args.exclude_tag.is_empty() {
+            println!("Dropped by tag filter: {}", filtered_tags);
+        }
+        if !args.product.is_empty() {
+            println!("Dropped by product filter: {}", filtered_products);
+        }
+        let tag_counts = tag_summary(&all_results);
+        if !tag_counts.is_empty() {
+            let summary: Vec<String> = tag_counts.iter().map(|(tag, count)| format!("{} {}", tag, count)).collect();
+            println!("IPs per tag: {}", summary.join(", "));
+        }
+        if args.recurse {
+            println!("Hosts discovered via recursion: {}", discovered_hosts);
+        }
+        if !resolution_failures.is_empty() {
+            let mut failures: Vec<_> = resolution_failures.iter().collect();
+            failures.sort();
+            let summary: Vec<String> = failures.iter().map(|(kind, count)| format!("{} {}", kind, count)).collect();
+            println!("Resolution failures: {}", summary.join(", "));
+        }
+        println!("DNS cache hits: {}", resolver.cache_hits());
         println!("Successful queries: {}", successful_queries);
         println!("Failed queries: {}", failed_queries);
         println!("Total ports found: {}", total_ports);
         if args.uniq.is_some() {
             println!("Unique ports found (excluding 80,443): {}", unique_ports);
         }
+        println!("IPs shared by multiple hosts: {}", shared_backends.len());
+        if args.verbose {
+            for (ip, hosts) in &shared_backends {
+                println!("  {} <- {}", ip, hosts.join(", "));
+            }
+        }
+        if args.http_probe.is_some() {
+            println!("HTTP probes answered: {}/{}", probes_answered, probes_sent);
+        }
         println!("Scan duration: {:.2}s", elapsed.as_secs_f64());
         println!("Average ports per host: {:.2}", if processed_hosts > 0 { total_ports as f64 / processed_hosts as f64 } else { 0.0 });
         println!("Query success rate: {:.1}%", if (successful_queries + failed_queries) > 0 { (successful_queries as f64 / (successful_queries + failed_queries) as f64) * 100.0 } else { 0.0 });
     }
 
+    if let Some(db_filename) = &args.history_db {
+        let run_id = history::record_run(db_filename, &input, started_at, &all_results)?;
+        if args.debug {
+            println!("Recorded run {} in {}", run_id, db_filename);
+        }
+    }
+
+    if let Some(baseline) = &baseline {
+        let changes = diff::compare(baseline, &all_results);
+        println!("\n--- Changes since baseline ---");
+        if changes.is_empty() {
+            println!("No changes");
+        } else {
+            changes.print();
+        }
+    }
+
+    let mut webhook_events = 0;
+    if let Some(sink) = &webhook {
+        // Without a baseline, every open port, CVE and tag is new
+        let empty = HashMap::new();
+        let timestamp = started_at.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let events = diff::compare(baseline.as_ref().unwrap_or(&empty), &all_results).events(timestamp);
+        if !events.is_empty() {
+            webhook_events = sink.send(&events, args.verbose).await;
+        }
+    }
+
+    if let (Some(by), false) = (args.group_by, args.silent) {
+        geo::print_groups(&all_results, by);
+    }
+
     if !args.silent {
         println!("\nResults saved to: {}", output_filename);
         if let Some(unique_filename) = &args.uniq {
             println!("Unique results (excluding ports 80,443) saved to: {}", unique_filename);
+        }
+        if !honeypots.is_empty() {
+            println!("Warning: {} honeypot IPs in the results: {}", honeypots.len(), honeypots.join(", "));
+        }
+        if args.wildcards.is_some() && collapsed_wildcards > 0 {
+            println!("Collapsed {} wildcard DNS entries", collapsed_wildcards);
+        }
+        if let Some(json_filename) = &args.json {
+            println!("Host mapping saved to: {}", json_filename);
+        }
+        if let Some(report_filename) = &args.vuln_report {
+            println!("Vulnerability report ({} CVEs) saved to: {}", reported_cves, report_filename);
+        }
+        if let Some(csv_filename) = &args.products_csv {
+            println!("Products ({} rows) saved to: {}", product_rows, csv_filename);
+        }
+        if let Some(html_filename) = &args.html {
+            println!("HTML report saved to: {}", html_filename);
+        }
+        if let Some(url) = &args.webhook {
+            println!("Delivered {} change events to: {}", webhook_events, url);
+        }
+        if let Some(db_filename) = &args.history_db {
+            println!("Run recorded in: {}", db_filename);
+        }
+        if let Some(probe_filename) = &args.http_probe {
+            println!("HTTP probe results saved to: {}", probe_filename);
+        }
+        if args.uniq.is_some() {
             println!("Found {} open ports ({} unique) across {} hosts in {:.2}s", total_ports, unique_ports, processed_hosts, elapsed.as_secs_f64());
         } else {
             println!("Found {} open ports across {} hosts in {:.2}s", total_ports, processed_hosts, elapsed.as_secs_f64());
         }
     }
 
-    Ok(())
+    Ok(all_results)
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internetdb_url_leaves_ipv6_unbracketed() {
+        assert_eq!(internetdb_url("2001:db8::1"), "https://internetdb.shodan.io/2001:db8::1");
+        assert_eq!(internetdb_url("192.0.2.1"), "https://internetdb.shodan.io/192.0.2.1");
+    }
+
+    #[test]
+    fn format_host_port_brackets_only_ipv6() {
+        assert_eq!(format_host_port("2001:db8::1", 443), "[2001:db8::1]:443");
+        assert_eq!(format_host_port("192.0.2.1", 443), "192.0.2.1:443");
+        assert_eq!(format_host_port("example.com", 8080), "example.com:8080");
+    }
+}
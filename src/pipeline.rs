@@ -0,0 +1,139 @@
+use crate::dns::{DnsResolver, Resolution, ResolutionFailure};
+use crate::record::Discovery;
+use crate::wildcard::WildcardDetector;
+use hickory_resolver::config::LookupIpStrategy;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Semaphore};
+
+/// A host waiting to be resolved and queried
+pub struct Target {
+    pub host: String,
+    pub depth: usize,
+    pub discovered_from: Option<Discovery>,
+    /// Ports given with the host in the input (`host:port` or a URL);
+    /// `None` when any line listed the host without a port
+    pub input_ports: Option<Vec<u16>>,
+}
+
+/// A target coming out of the resolution stage
+pub struct Resolved {
+    pub target: Target,
+    pub result: Result<Resolution, ResolutionFailure>,
+    /// Parent zone whose wildcard is all the target resolved to, when wildcard
+    /// detection is on
+    pub wildcard: Option<String>,
+}
+
+/// Resolve `host` to its IPs. IP literals are returned as-is and never sent to DNS.
+/// Addresses outside the requested family are dropped and duplicates removed.
+pub async fn resolve_host(resolver: &DnsResolver, host: &str, ip_strategy: LookupIpStrategy) -> Result<Resolution, ResolutionFailure> {
+    let resolution = match host.parse::<IpAddr>() {
+        Ok(ip) => Resolution {
+            ips: vec![ip],
+            cnames: vec![],
+        },
+        Err(_) => resolver.resolve(host).await?,
+    };
+    let mut ips: Vec<IpAddr> = vec![];
+    for addr in resolution.ips {
+        let wanted = match ip_strategy {
+            LookupIpStrategy::Ipv4Only => addr.is_ipv4(),
+            LookupIpStrategy::Ipv6Only => addr.is_ipv6(),
+            _ => true,
+        };
+        if wanted && !ips.contains(&addr) {
+            ips.push(addr);
+        }
+    }
+    Ok(Resolution {
+        ips,
+        cnames: resolution.cnames,
+    })
+}
+
+/// Start the resolution stage. Targets sent on the returned sender are
+/// resolved up to `concurrency` at a time, and come back on the returned
+/// receiver in completion order. The output channel is bounded, so DNS runs
+/// at most `buffer` targets ahead of the InternetDB queries. With `wildcards`,
+/// each resolved name is also checked against its parent zone's wildcard.
+///
+/// The input side is unbounded so the query loop can queue discovered hosts
+/// without ever waiting on the stage that is waiting on it.
+pub fn spawn_resolver(
+    resolver: Arc<DnsResolver>,
+    ip_strategy: LookupIpStrategy,
+    wildcards: Option<Arc<WildcardDetector>>,
+    concurrency: usize,
+    buffer: usize,
+) -> (mpsc::UnboundedSender<Target>, mpsc::Receiver<Resolved>) {
+    let (target_tx, mut target_rx) = mpsc::unbounded_channel::<Target>();
+    let (resolved_tx, resolved_rx) = mpsc::channel(buffer.max(1));
+    let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+
+    tokio::spawn(async move {
+        while let Some(target) = target_rx.recv().await {
+            let Ok(permit) = permits.clone().acquire_owned().await else {
+                break;
+            };
+            let resolver = resolver.clone();
+            let wildcards = wildcards.clone();
+            let resolved_tx = resolved_tx.clone();
+            tokio::spawn(async move {
+                let result = resolve_host(&resolver, &target.host, ip_strategy).await;
+                let wildcard = match (&wildcards, &result) {
+                    (Some(detector), Ok(resolution)) => detector.check(&resolver, &target.host, &resolution.ips).await,
+                    _ => None,
+                };
+                let _ = resolved_tx.send(Resolved { target, result, wildcard }).await;
+                drop(permit);
+            });
+        }
+    });
+
+    (target_tx, resolved_rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::tests::{from_servers, silent, stub};
+    use crate::dns::ResolverMode;
+
+    fn ips(list: &[&str]) -> Vec<IpAddr> {
+        list.iter().map(|ip| ip.parse().unwrap()).collect()
+    }
+
+    async fn resolved_ips(resolver: &DnsResolver, host: &str, ip_strategy: LookupIpStrategy) -> Vec<IpAddr> {
+        resolve_host(resolver, host, ip_strategy).await.ok().unwrap().ips
+    }
+
+    #[tokio::test]
+    async fn ip_literals_skip_dns_and_respect_family() {
+        // Any query would time out against a server that never answers
+        let (_socket, addr) = silent().await;
+        let resolver = from_servers("literals", &[addr], ResolverMode::Failover, LookupIpStrategy::Ipv4AndIpv6);
+
+        assert_eq!(resolved_ips(&resolver, "192.0.2.1", LookupIpStrategy::Ipv4AndIpv6).await, ips(&["192.0.2.1"]));
+        assert_eq!(resolved_ips(&resolver, "2001:db8::1", LookupIpStrategy::Ipv4AndIpv6).await, ips(&["2001:db8::1"]));
+        assert_eq!(resolved_ips(&resolver, "192.0.2.1", LookupIpStrategy::Ipv4Only).await, ips(&["192.0.2.1"]));
+        assert!(resolved_ips(&resolver, "2001:db8::1", LookupIpStrategy::Ipv4Only).await.is_empty());
+        assert_eq!(resolved_ips(&resolver, "2001:db8::1", LookupIpStrategy::Ipv6Only).await, ips(&["2001:db8::1"]));
+        assert!(resolved_ips(&resolver, "192.0.2.1", LookupIpStrategy::Ipv6Only).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn resolved_addresses_are_deduplicated_and_filtered() {
+        let answers = ips(&["192.0.2.1", "192.0.2.1", "2001:db8::1", "192.0.2.2", "2001:db8::1"]);
+        let server = stub(answers).await;
+        let resolver = from_servers("dedup", &[server], ResolverMode::Failover, LookupIpStrategy::Ipv4AndIpv6);
+
+        let all = resolved_ips(&resolver, "a.test.", LookupIpStrategy::Ipv4AndIpv6).await;
+        assert_eq!(all.len(), 3);
+        for ip in ips(&["192.0.2.1", "192.0.2.2", "2001:db8::1"]) {
+            assert!(all.contains(&ip));
+        }
+        assert_eq!(resolved_ips(&resolver, "b.test.", LookupIpStrategy::Ipv4Only).await, ips(&["192.0.2.1", "192.0.2.2"]));
+        assert_eq!(resolved_ips(&resolver, "c.test.", LookupIpStrategy::Ipv6Only).await, ips(&["2001:db8::1"]));
+    }
+}
@@ -0,0 +1,185 @@
+use reqwest::header::{HeaderName, CONTENT_LENGTH, LOCATION, SERVER};
+use reqwest::redirect::Policy;
+use reqwest::Client;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+// Ports that commonly serve HTTP(S) and are worth probing
+const WEB_PORTS: &[u16] = &[
+    80, 81, 443, 591, 2082, 2083, 2086, 2087, 3000, 4443, 5000, 5601, 7001, 7443, 8000, 8008, 8080,
+    8081, 8088, 8443, 8888, 9000, 9090, 9443, 10000, 10443,
+];
+
+// Ports where HTTPS is tried first
+const TLS_PORTS: &[u16] = &[443, 2083, 2087, 4443, 7443, 8443, 9443, 10000, 10443];
+
+// Cap on how much of the body is read when looking for a title
+const MAX_BODY_BYTES: usize = 256 * 1024;
+
+pub struct ProbeResult {
+    pub url: String,
+    pub status: u16,
+    pub title: Option<String>,
+    pub server: Option<String>,
+    pub location: Option<String>,
+    pub content_length: u64,
+    /// The body had no Content-Length and was cut off at `MAX_BODY_BYTES`,
+    /// so `content_length` is only a lower bound
+    pub truncated: bool,
+}
+
+impl ProbeResult {
+    /// Format the result as a single httpx-style line
+    pub fn to_line(&self) -> String {
+        let mut line = format!("{} [{}]", self.url, self.status);
+        if let Some(title) = &self.title {
+            line.push_str(&format!(" [{}]", title));
+        }
+        if let Some(server) = &self.server {
+            line.push_str(&format!(" [{}]", server));
+        }
+        if let Some(location) = &self.location {
+            line.push_str(&format!(" [-> {}]", location));
+        }
+        if self.truncated {
+            line.push_str(&format!(" [>{}]", self.content_length));
+        } else {
+            line.push_str(&format!(" [{}]", self.content_length));
+        }
+        line
+    }
+}
+
+pub fn is_web_port(port: u16) -> bool {
+    WEB_PORTS.contains(&port)
+}
+
+/// Build a client that connects `host` to `ip` while keeping the hostname in
+/// the URL, so SNI and the Host header match what a browser would send.
+pub fn build_probe_client(host: &str, ip: &str) -> reqwest::Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(5))
+        .redirect(Policy::none())
+        .danger_accept_invalid_certs(true);
+    if host.parse::<IpAddr>().is_err() {
+        if let Ok(addr) = ip.parse::<IpAddr>() {
+            // The port is taken from the URL, not from this address
+            builder = builder.resolve(host, SocketAddr::new(addr, 0));
+        }
+    }
+    builder.build()
+}
+
+async fn fetch(client: &Client, url: &str, user_agent: &str) -> Result<ProbeResult, reqwest::Error> {
+    let mut resp = client.get(url).header("User-Agent", user_agent).send().await?;
+
+    let status = resp.status().as_u16();
+    let header = |name: HeaderName| resp.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let server = header(SERVER);
+    let location = header(LOCATION);
+    let declared_length = header(CONTENT_LENGTH).and_then(|v| v.parse::<u64>().ok());
+
+    // Stop reading once there is enough to find the title
+    let mut body: Vec<u8> = vec![];
+    while body.len() < MAX_BODY_BYTES {
+        match resp.chunk().await? {
+            Some(chunk) => body.extend_from_slice(&chunk),
+            None => break,
+        }
+    }
+    // Without a declared length, a body cut off at the cap has an unknown size
+    let truncated = declared_length.is_none() && body.len() >= MAX_BODY_BYTES;
+    let content_length = declared_length.unwrap_or(body.len() as u64);
+    let text = String::from_utf8_lossy(&body[..body.len().min(MAX_BODY_BYTES)]);
+
+    Ok(ProbeResult {
+        url: url.to_string(),
+        status,
+        title: extract_title(&text),
+        server,
+        location,
+        content_length,
+        truncated,
+    })
+}
+
+/// Probe `host:port`, trying the scheme most likely to answer first and
+/// falling back to the other one on connection errors.
+pub async fn probe(client: &Client, host: &str, port: u16, user_agent: &str) -> Result<ProbeResult, Box<dyn std::error::Error + Send + Sync>> {
+    let schemes = if TLS_PORTS.contains(&port) {
+        ["https", "http"]
+    } else {
+        ["http", "https"]
+    };
+    let mut last_err = None;
+    for scheme in schemes {
+        let url = format!("{}://{}/", scheme, crate::format_host_port(host, port));
+        match fetch(client, &url, user_agent).await {
+            Ok(result) => return Ok(result),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(format!("HTTP probe failed for {}:{}: {}", host, port, last_err.map(|e| e.to_string()).unwrap_or_default()).into())
+}
+
+fn extract_title(body: &str) -> Option<String> {
+    let lower = body.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = body[start..end].split_whitespace().collect::<Vec<_>>().join(" ");
+    if title.is_empty() {
+        None
+    } else {
+        Some(title)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_title_variants() {
+        assert_eq!(extract_title("<html><TITLE lang=x>Admin Panel</Title></html>").as_deref(), Some("Admin Panel"));
+        assert_eq!(extract_title("<title>\n  Welcome   to\n\tnginx!  </title>").as_deref(), Some("Welcome to nginx!"));
+        assert_eq!(extract_title("<title>Never closed"), None);
+        assert_eq!(extract_title("<title>   </title>"), None);
+        assert_eq!(extract_title("<h1>No title</h1>"), None);
+    }
+
+    fn result() -> ProbeResult {
+        ProbeResult {
+            url: "http://example.com:80/".to_string(),
+            status: 301,
+            title: None,
+            server: Some("nginx".to_string()),
+            location: Some("https://example.com/".to_string()),
+            content_length: 162,
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn to_line_formats() {
+        assert_eq!(result().to_line(), "http://example.com:80/ [301] [nginx] [-> https://example.com/] [162]");
+
+        let page = ProbeResult {
+            url: "https://example.com:443/".to_string(),
+            status: 200,
+            title: Some("Example Domain".to_string()),
+            location: None,
+            content_length: 1256,
+            ..result()
+        };
+        assert_eq!(page.to_line(), "https://example.com:443/ [200] [Example Domain] [nginx] [1256]");
+
+        let cut = ProbeResult {
+            location: None,
+            content_length: MAX_BODY_BYTES as u64,
+            truncated: true,
+            ..result()
+        };
+        assert_eq!(cut.to_line(), "http://example.com:80/ [301] [nginx] [>262144]");
+    }
+}
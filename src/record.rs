@@ -0,0 +1,152 @@
+use crate::cpe::Product;
+use crate::dns::ResolutionFailure;
+use crate::geo::GeoInfo;
+use crate::ShodanResult;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Everything learned about one input host, written as one JSON line
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HostRecord {
+    pub host: String,
+    pub ips: Vec<IpRecord>,
+    /// Set when the host was not in the input but found through `--recurse`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discovered_from: Option<Discovery>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution_error: Option<ResolutionFailure>,
+    /// CNAME chain followed from `host` during resolution
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cnames: Vec<String>,
+    /// Parent zone whose wildcard is all this host resolved to. Only the first
+    /// such host of each zone has its IPs queried; the others are left empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wildcard: Option<String>,
+}
+
+/// Lineage of a recursively discovered host: which host and IP introduced it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Discovery {
+    pub host: String,
+    pub ip: String,
+    pub depth: usize,
+}
+
+/// One resolved IP of a host together with what InternetDB reported for it
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IpRecord {
+    /// `hostnames` in here are the names InternetDB reported
+    #[serde(flatten)]
+    pub result: ShodanResult,
+    /// Vendor, product and version parsed from the reported `cpes`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub products: Vec<Product>,
+    /// Service names of the open ports (`--services`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub services: BTreeMap<u16, String>,
+    /// Names from a PTR lookup of the IP (`--ptr`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ptr: Vec<String>,
+    /// Other input hosts that resolved to the same IP
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub shared_with: Vec<String>,
+    /// CDN/WAF provider fronting this IP, matched by range or CNAME
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cdn: Option<String>,
+    /// ASN, country and city from the MMDB files (`--asn-mmdb`, `--geo-mmdb`)
+    #[serde(flatten)]
+    pub geo: GeoInfo,
+    /// InternetDB tagged the IP as a honeypot, so its ports are unreliable
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub honeypot: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl HostRecord {
+    pub fn new(host: &str) -> Self {
+        HostRecord {
+            host: host.to_string(),
+            ips: vec![],
+            discovered_from: None,
+            resolution_error: None,
+            cnames: vec![],
+            wildcard: None,
+        }
+    }
+}
+
+impl IpRecord {
+    pub fn found(result: ShodanResult) -> Self {
+        IpRecord {
+            products: result.cpes.iter().filter_map(|cpe| Product::parse(cpe)).collect(),
+            result,
+            services: BTreeMap::new(),
+            ptr: vec![],
+            shared_with: vec![],
+            cdn: None,
+            geo: GeoInfo::default(),
+            honeypot: false,
+            error: None,
+        }
+    }
+
+    pub fn failed(ip: &str, error: String) -> Self {
+        IpRecord {
+            result: ShodanResult {
+                ip: ip.to_string(),
+                ..Default::default()
+            },
+            products: vec![],
+            services: BTreeMap::new(),
+            ptr: vec![],
+            shared_with: vec![],
+            cdn: None,
+            geo: GeoInfo::default(),
+            honeypot: false,
+            error: Some(error),
+        }
+    }
+}
+
+/// Fill in `shared_with` for every IP that more than one input host resolved to.
+/// Returns the shared IPs with the hosts behind each of them.
+pub fn link_shared_backends(records: &mut HashMap<String, HostRecord>) -> Vec<(String, Vec<String>)> {
+    let mut hosts_by_ip: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for record in records.values() {
+        for ip_record in &record.ips {
+            hosts_by_ip
+                .entry(ip_record.result.ip.clone())
+                .or_default()
+                .insert(record.host.clone());
+        }
+    }
+
+    for record in records.values_mut() {
+        for ip_record in &mut record.ips {
+            if let Some(hosts) = hosts_by_ip.get(&ip_record.result.ip) {
+                ip_record.shared_with = hosts.iter().filter(|h| **h != record.host).cloned().collect();
+            }
+        }
+    }
+
+    let mut shared: Vec<(String, Vec<String>)> = hosts_by_ip
+        .into_iter()
+        .filter(|(_, hosts)| hosts.len() > 1)
+        .map(|(ip, hosts)| (ip, hosts.into_iter().collect()))
+        .collect();
+    shared.sort();
+    shared
+}
+
+/// A scan from `(host, ips)` pairs, with each IP written as it appears in `--json` output
+#[cfg(test)]
+pub fn scan_from_json(hosts: &[(&str, serde_json::Value)]) -> HashMap<String, HostRecord> {
+    hosts
+        .iter()
+        .map(|(host, ips)| {
+            let record: HostRecord = serde_json::from_value(serde_json::json!({ "host": host, "ips": ips })).unwrap();
+            (host.to_string(), record)
+        })
+        .collect()
+}
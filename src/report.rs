@@ -0,0 +1,273 @@
+use crate::record::HostRecord;
+use crate::vuln::CveDb;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Ports shown in the frequency chart
+const TOP_PORTS: usize = 20;
+
+const STYLE: &str = "
+body { font-family: -apple-system, 'Segoe UI', Helvetica, Arial, sans-serif; margin: 2em auto; max-width: 1100px; color: #222; }
+h1 { margin-bottom: 0.2em; }
+.meta { color: #666; margin-top: 0; }
+.cards { display: flex; flex-wrap: wrap; gap: 1em; margin: 1.5em 0; }
+.card { border: 1px solid #ddd; border-radius: 6px; padding: 0.8em 1.2em; min-width: 110px; }
+.card b { display: block; font-size: 1.8em; }
+table { border-collapse: collapse; width: 100%; margin-bottom: 1.5em; }
+th, td { text-align: left; padding: 0.35em 0.6em; border-bottom: 1px solid #eee; vertical-align: top; }
+th { background: #f5f5f5; }
+.bar { display: flex; align-items: center; margin: 0.2em 0; }
+.bar span { width: 8em; font-family: monospace; }
+.bar div { background: #4a78c2; color: #fff; padding: 0.1em 0.4em; font-size: 0.85em; }
+.tag { display: inline-block; background: #eef; border-radius: 3px; padding: 0 0.4em; margin: 0 0.2em 0.2em 0; font-size: 0.85em; }
+.warn { background: #fdd; }
+.mono { font-family: monospace; }
+input { padding: 0.4em; width: 20em; margin-bottom: 0.8em; }
+";
+
+// Filters the host table as the user types
+const SCRIPT: &str = "
+document.getElementById('filter').addEventListener('input', function (e) {
+  var q = e.target.value.toLowerCase();
+  document.querySelectorAll('#hosts tbody tr').forEach(function (row) {
+    row.style.display = row.textContent.toLowerCase().indexOf(q) === -1 ? 'none' : '';
+  });
+});
+";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn tags_html(values: &[String], warn: &str) -> String {
+    values
+        .iter()
+        .map(|value| {
+            let class = if value.eq_ignore_ascii_case(warn) { "tag warn" } else { "tag" };
+            format!("<span class=\"{}\">{}</span>", class, escape(value))
+        })
+        .collect()
+}
+
+/// Write a single-file HTML report: summary figures, a port frequency chart,
+/// tag and CVE breakdowns, and a filterable table of every host and IP.
+pub fn write_html(path: &str, input: &str, records: &HashMap<String, HostRecord>, cve_db: &CveDb, elapsed: Duration) -> io::Result<()> {
+    let mut hosts: Vec<&HostRecord> = records.values().collect();
+    hosts.sort_by(|a, b| a.host.cmp(&b.host));
+
+    let mut ips = BTreeSet::new();
+    let mut port_counts: BTreeMap<u16, usize> = BTreeMap::new();
+    let mut cve_hosts: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    let mut open_ports = 0;
+    for record in &hosts {
+        for ip_record in &record.ips {
+            ips.insert(ip_record.result.ip.as_str());
+            open_ports += ip_record.result.ports.len();
+            for port in &ip_record.result.ports {
+                *port_counts.entry(*port).or_default() += 1;
+            }
+            for cve in &ip_record.result.vulns {
+                cve_hosts.entry(cve).or_default().insert(&record.host);
+            }
+        }
+    }
+    let tag_counts = crate::tag_summary(records);
+    let generated = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+
+    let mut html = String::new();
+    // Writing to a String can't fail
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>qport report: {}</title>\n<style>{}</style>\n</head>\n<body>\n",
+        escape(input),
+        STYLE
+    );
+    let _ = write!(
+        html,
+        "<h1>qport report</h1>\n<p class=\"meta\">Input {} &middot; generated {} &middot; scan took {:.1}s &middot; data from Shodan InternetDB</p>\n",
+        escape(input),
+        crate::diff::format_utc(generated),
+        elapsed.as_secs_f64()
+    );
+
+    let _ = write!(html, "<div class=\"cards\">");
+    for (label, value) in [
+        ("Hosts", hosts.len()),
+        ("IPs", ips.len()),
+        ("Open ports", open_ports),
+        ("Distinct ports", port_counts.len()),
+        ("CVEs", cve_hosts.len()),
+    ] {
+        let _ = write!(html, "<div class=\"card\"><b>{}</b>{}</div>", value, label);
+    }
+    let _ = writeln!(html, "</div>");
+
+    let mut top_ports: Vec<(u16, usize)> = port_counts.iter().map(|(port, count)| (*port, *count)).collect();
+    top_ports.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    top_ports.truncate(TOP_PORTS);
+    if let Some(&(_, max)) = top_ports.first() {
+        let _ = writeln!(html, "<h2>Most common ports</h2>");
+        for (port, count) in &top_ports {
+            let _ = writeln!(
+                html,
+                "<div class=\"bar\"><span>{}</span><div style=\"width: {}%\">{}</div></div>",
+                port,
+                (count * 100 / max).max(1),
+                count
+            );
+        }
+    }
+
+    if !tag_counts.is_empty() {
+        let _ = writeln!(html, "<h2>Tags</h2>\n<table><thead><tr><th>Tag</th><th>IPs</th></tr></thead><tbody>");
+        for (tag, count) in &tag_counts {
+            let class = if tag.eq_ignore_ascii_case("honeypot") { " class=\"warn\"" } else { "" };
+            let _ = writeln!(html, "<tr{}><td>{}</td><td>{}</td></tr>", class, escape(tag), count);
+        }
+        let _ = writeln!(html, "</tbody></table>");
+    }
+
+    if !cve_hosts.is_empty() {
+        // Highest CVSS first, unscored CVEs last
+        let mut cves: Vec<(&str, &BTreeSet<&str>)> = cve_hosts.iter().map(|(cve, hosts)| (*cve, hosts)).collect();
+        let score = |cve: &str| cve_db.get(cve).and_then(|info| info.cvss).unwrap_or(-1.0);
+        cves.sort_by(|a, b| score(b.0).total_cmp(&score(a.0)).then(a.0.cmp(b.0)));
+        let _ = writeln!(
+            html,
+            "<h2>Vulnerabilities</h2>\n<table><thead><tr><th>CVE</th><th>CVSS</th><th>KEV</th><th>Title</th><th>Hosts</th></tr></thead><tbody>"
+        );
+        for (cve, affected) in cves {
+            let info = cve_db.get(cve);
+            let cvss = info.and_then(|info| info.cvss).map(|s| format!("{:.1}", s)).unwrap_or_default();
+            let kev = if info.is_some_and(|info| info.kev) { "yes" } else { "" };
+            let title = info.and_then(|info| info.title.as_deref()).unwrap_or("");
+            let affected: Vec<&str> = affected.iter().copied().collect();
+            let _ = writeln!(
+                html,
+                "<tr><td class=\"mono\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape(cve),
+                cvss,
+                kev,
+                escape(title),
+                escape(&affected.join(", "))
+            );
+        }
+        let _ = writeln!(html, "</tbody></table>");
+    }
+
+    let _ = writeln!(
+        html,
+        "<h2>Hosts</h2>\n<input id=\"filter\" placeholder=\"Filter hosts, IPs, ports, tags...\">\n<table id=\"hosts\"><thead><tr><th>Host</th><th>IP</th><th>Ports</th><th>Tags</th><th>CVEs</th><th>Notes</th></tr></thead><tbody>"
+    );
+    for record in &hosts {
+        if record.ips.is_empty() {
+            let note = match (&record.resolution_error, &record.wildcard) {
+                (Some(failure), _) => format!("resolution failed: {}", failure),
+                (None, Some(zone)) => format!("wildcard of {}", zone),
+                (None, None) => "no IPs queried".to_string(),
+            };
+            let _ = writeln!(html, "<tr><td>{}</td><td></td><td></td><td></td><td></td><td>{}</td></tr>", escape(&record.host), escape(&note));
+            continue;
+        }
+        for ip_record in &record.ips {
+            let result = &ip_record.result;
+            let ports: Vec<String> = result
+                .ports
+                .iter()
+                .map(|port| match ip_record.services.get(port) {
+                    Some(service) => format!("{}/{}", port, service),
+                    None => port.to_string(),
+                })
+                .collect();
+            let mut notes = vec![];
+            if let Some(zone) = &record.wildcard {
+                notes.push(format!("answers for *.{}", zone));
+            }
+            if let Some(provider) = &ip_record.cdn {
+                notes.push(format!("CDN: {}", provider));
+            }
+            if let Some(country) = &ip_record.geo.country {
+                notes.push(country.clone());
+            }
+            if let Some(org) = &ip_record.geo.as_org {
+                notes.push(org.clone());
+            }
+            if let Some(error) = &ip_record.error {
+                notes.push(format!("query failed: {}", error));
+            }
+            let class = if ip_record.honeypot { " class=\"warn\"" } else { "" };
+            let _ = writeln!(
+                html,
+                "<tr{}><td>{}</td><td class=\"mono\">{}</td><td class=\"mono\">{}</td><td>{}</td><td class=\"mono\">{}</td><td>{}</td></tr>",
+                class,
+                escape(&record.host),
+                escape(&result.ip),
+                escape(&ports.join(", ")),
+                tags_html(&result.tags, "honeypot"),
+                escape(&result.vulns.join(", ")),
+                escape(&notes.join(" · "))
+            );
+        }
+    }
+    let _ = writeln!(html, "</tbody></table>\n<script>{}</script>\n</body>\n</html>", SCRIPT);
+
+    fs::write(path, html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::scan_from_json;
+    use serde_json::json;
+
+    #[test]
+    fn html_escapes_scan_data_and_flags_honeypots() {
+        let cve_path = std::env::temp_dir().join(format!("qport-report-{}-cves.json", std::process::id()));
+        fs::write(&cve_path, json!([{ "id": "CVE-2021-44228", "cvss": 10.0, "title": "<script>alert(\"title\")</script> & co" }]).to_string()).unwrap();
+        let mut cve_db = CveDb::default();
+        cve_db.load(cve_path.to_str().unwrap()).unwrap();
+        fs::remove_file(&cve_path).unwrap();
+
+        let records = scan_from_json(&[
+            (
+                "<script>alert(\"host\")</script>.example.com",
+                json!([{
+                    "ip": "192.0.2.1",
+                    "ports": [80],
+                    "hostnames": [],
+                    "tags": ["<script>alert(\"tag\")</script>", "honeypot"],
+                    "vulns": ["CVE-2021-44228"],
+                    "honeypot": true,
+                }]),
+            ),
+            ("b.example.com", json!([{ "ip": "192.0.2.2", "ports": [443], "hostnames": [] }])),
+        ]);
+
+        let path = std::env::temp_dir().join(format!("qport-report-{}-report.html", std::process::id()));
+        write_html(path.to_str().unwrap(), "hosts \"<script>\".txt", &records, &cve_db, Duration::from_secs(1)).unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        for raw in ["<script>alert", "alert(\"", "\"<script>\""] {
+            assert!(!html.contains(raw), "unescaped {:?} in report", raw);
+        }
+        assert!(html.contains("&lt;script&gt;alert(&quot;host&quot;)&lt;/script&gt;.example.com"));
+        assert!(html.contains("&lt;script&gt;alert(&quot;tag&quot;)&lt;/script&gt;"));
+        assert!(html.contains("&lt;script&gt;alert(&quot;title&quot;)&lt;/script&gt; &amp; co"));
+        assert!(html.contains("<title>qport report: hosts &quot;&lt;script&gt;&quot;.txt</title>"));
+        // Only the report's own filter script is left
+        assert_eq!(html.matches("<script>").count(), 1);
+
+        // The honeypot IP's row and the honeypot tag are flagged, the other host isn't
+        assert!(html.contains("<tr class=\"warn\"><td>&lt;script&gt;alert(&quot;host&quot;)&lt;/script&gt;.example.com</td><td class=\"mono\">192.0.2.1</td>"));
+        assert!(html.contains("<tr class=\"warn\"><td>honeypot</td><td>1</td></tr>"));
+        assert!(html.contains("<span class=\"tag warn\">honeypot</span>"));
+        assert!(html.contains("<tr><td>b.example.com</td><td class=\"mono\">192.0.2.2</td>"));
+    }
+}
@@ -0,0 +1,248 @@
+use ipnet::IpNet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::net::IpAddr;
+
+/// Whether `name` is `domain` itself or one of its subdomains
+pub fn matches_domain(name: &str, domain: &str) -> bool {
+    let domain = domain.trim_start_matches("*.").trim_end_matches('.');
+    name == domain || name.ends_with(&format!(".{}", domain))
+}
+
+enum DomainRule {
+    /// `example.com` matches only that name
+    Exact(String),
+    /// `*.example.com` matches any subdomain, but not `example.com` itself
+    Wildcard(String),
+}
+
+/// Rules loaded from a scope or exclude file
+#[derive(Default)]
+pub struct RuleSet {
+    domains: Vec<DomainRule>,
+    networks: Vec<IpNet>,
+}
+
+impl RuleSet {
+    /// Load rules from a file with one domain, `*.domain`, CIDR or IP per line.
+    /// Blank lines and `#` comments are ignored.
+    pub fn load(path: &str) -> io::Result<Self> {
+        let mut rules = RuleSet::default();
+        let reader = BufReader::new(File::open(path)?);
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let rule = line.split('#').next().unwrap_or("").trim().to_lowercase();
+            if rule.is_empty() {
+                continue;
+            }
+            if let Ok(net) = rule.parse::<IpNet>() {
+                rules.networks.push(net);
+            } else if let Ok(ip) = rule.parse::<IpAddr>() {
+                rules.networks.push(IpNet::from(ip));
+            } else if let Some(domain) = rule.strip_prefix("*.") {
+                rules.domains.push(DomainRule::Wildcard(domain.trim_end_matches('.').to_string()));
+            } else if rule.contains('/') || rule.contains(' ') {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: invalid scope rule '{}'", path, n + 1, rule),
+                ));
+            } else {
+                rules.domains.push(DomainRule::Exact(rule.trim_end_matches('.').to_string()));
+            }
+        }
+        Ok(rules)
+    }
+
+    /// The domain rules as written (`example.com`, `*.example.com`)
+    pub fn domain_rules(&self) -> Vec<String> {
+        self.domains
+            .iter()
+            .map(|rule| match rule {
+                DomainRule::Exact(d) => d.clone(),
+                DomainRule::Wildcard(d) => format!("*.{}", d),
+            })
+            .collect()
+    }
+
+    fn match_domain(&self, name: &str) -> Option<String> {
+        self.domains.iter().find_map(|rule| match rule {
+            DomainRule::Exact(d) if name == d => Some(d.clone()),
+            DomainRule::Wildcard(d) if name.ends_with(&format!(".{}", d)) => Some(format!("*.{}", d)),
+            _ => None,
+        })
+    }
+
+    fn match_ip(&self, ip: IpAddr) -> Option<String> {
+        self.networks.iter().find(|net| net.contains(&ip)).map(|net| net.to_string())
+    }
+}
+
+/// Allow and deny lists applied to every target before it is queried
+#[derive(Default)]
+pub struct Scope {
+    pub allow: Option<RuleSet>,
+    pub deny: Option<RuleSet>,
+}
+
+impl Scope {
+    pub fn is_active(&self) -> bool {
+        self.allow.is_some() || self.deny.is_some()
+    }
+
+    /// Check an input host before it is resolved. Names are held against the
+    /// domain rules, IP literals against the network rules.
+    pub fn check_host(&self, host: &str) -> Result<(), String> {
+        let host = host.trim_end_matches('.').to_lowercase();
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            if let Some(allow) = &self.allow {
+                if allow.match_ip(ip).is_none() {
+                    return Err("IP not in scope".to_string());
+                }
+            }
+            return self.check_excluded_ip(ip);
+        }
+
+        if let Some(rule) = self.deny.as_ref().and_then(|deny| deny.match_domain(&host)) {
+            return Err(format!("host matches exclude rule {}", rule));
+        }
+        if let Some(allow) = &self.allow {
+            // A scope made only of networks is enforced on the resolved IPs
+            if !allow.domains.is_empty() && allow.match_domain(&host).is_none() {
+                return Err("host not in scope".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a resolved IP before it is sent to InternetDB
+    pub fn check_ip(&self, ip: IpAddr) -> Result<(), String> {
+        if let Some(allow) = &self.allow {
+            // A scope made only of domains allows whatever its names resolve to
+            if !allow.networks.is_empty() && allow.match_ip(ip).is_none() {
+                return Err(format!("resolved IP {} outside allowed ranges", ip));
+            }
+        }
+        self.check_excluded_ip(ip)
+    }
+
+    fn check_excluded_ip(&self, ip: IpAddr) -> Result<(), String> {
+        match self.deny.as_ref().and_then(|deny| deny.match_ip(ip)) {
+            Some(net) => Err(format!("IP {} matches exclude rule {}", ip, net)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(name: &str, contents: &str) -> RuleSet {
+        let path = std::env::temp_dir().join(format!("qport-scope-{}-{}.txt", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        let rules = RuleSet::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        rules
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn load_skips_comments_and_rejects_garbage() {
+        let set = rules("load", "# scope\nExample.com.\n*.example.org # subs\n\n10.0.0.0/8\n192.0.2.1\n");
+        assert_eq!(set.domain_rules(), vec!["example.com", "*.example.org"]);
+        assert_eq!(set.networks.len(), 2);
+
+        let path = std::env::temp_dir().join(format!("qport-scope-{}-bad.txt", std::process::id()));
+        std::fs::write(&path, "example.com\n10.0.0.0/33\n").unwrap();
+        let err = RuleSet::load(path.to_str().unwrap()).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.to_string().ends_with(":2: invalid scope rule '10.0.0.0/33'"));
+    }
+
+    #[test]
+    fn wildcard_does_not_match_apex() {
+        let scope = Scope {
+            allow: Some(rules("wildcard", "*.example.com\n")),
+            deny: None,
+        };
+        assert!(scope.check_host("www.example.com").is_ok());
+        assert!(scope.check_host("a.b.example.com").is_ok());
+        assert!(scope.check_host("example.com").is_err());
+        assert!(scope.check_host("badexample.com").is_err());
+    }
+
+    #[test]
+    fn exact_rule_matches_only_that_name() {
+        let scope = Scope {
+            allow: Some(rules("exact", "example.com\n")),
+            deny: None,
+        };
+        assert!(scope.check_host("Example.COM.").is_ok());
+        assert!(scope.check_host("www.example.com").is_err());
+    }
+
+    #[test]
+    fn deny_beats_allow() {
+        let scope = Scope {
+            allow: Some(rules("allow", "*.example.com\n10.0.0.0/8\n")),
+            deny: Some(rules("deny", "admin.example.com\n10.1.0.0/16\n")),
+        };
+        assert!(scope.check_host("www.example.com").is_ok());
+        assert_eq!(scope.check_host("admin.example.com").unwrap_err(), "host matches exclude rule admin.example.com");
+        assert!(scope.check_host("10.2.3.4").is_ok());
+        assert!(scope.check_host("10.1.2.3").is_err());
+        assert!(scope.check_ip(ip("10.1.2.3")).is_err());
+    }
+
+    #[test]
+    fn network_only_scope() {
+        let scope = Scope {
+            allow: Some(rules("networks", "192.0.2.0/24\n2001:db8::/32\n")),
+            deny: None,
+        };
+        // Names pass until they resolve, then the IPs are checked
+        assert!(scope.check_host("anything.example").is_ok());
+        assert!(scope.check_host("192.0.2.7").is_ok());
+        assert!(scope.check_host("198.51.100.7").is_err());
+        assert!(scope.check_ip(ip("2001:db8::1")).is_ok());
+        assert!(scope.check_ip(ip("2001:db9::1")).is_err());
+    }
+
+    #[test]
+    fn domain_only_scope() {
+        let scope = Scope {
+            allow: Some(rules("domains", "example.com\n")),
+            deny: None,
+        };
+        assert!(scope.check_host("other.example").is_err());
+        // Whatever an in-scope name resolves to is allowed
+        assert!(scope.check_ip(ip("198.51.100.7")).is_ok());
+        // IP literals must be listed explicitly
+        assert!(scope.check_host("198.51.100.7").is_err());
+    }
+
+    #[test]
+    fn resolved_ip_outside_allowed_ranges() {
+        let scope = Scope {
+            allow: Some(rules("mixed", "*.example.com\n192.0.2.0/24\n")),
+            deny: None,
+        };
+        assert!(scope.check_host("www.example.com").is_ok());
+        assert!(scope.check_ip(ip("192.0.2.10")).is_ok());
+        assert_eq!(
+            scope.check_ip(ip("203.0.113.5")).unwrap_err(),
+            "resolved IP 203.0.113.5 outside allowed ranges"
+        );
+    }
+
+    #[test]
+    fn no_scope_allows_everything() {
+        let scope = Scope::default();
+        assert!(!scope.is_active());
+        assert!(scope.check_host("example.com").is_ok());
+        assert!(scope.check_ip(ip("203.0.113.5")).is_ok());
+    }
+}
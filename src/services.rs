@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+
+// Common TCP services, after IANA and nmap-services
+const BUNDLED_SERVICES: &[(u16, &str)] = &[
+    (21, "ftp"),
+    (22, "ssh"),
+    (23, "telnet"),
+    (25, "smtp"),
+    (53, "domain"),
+    (80, "http"),
+    (81, "http"),
+    (88, "kerberos"),
+    (110, "pop3"),
+    (111, "rpcbind"),
+    (135, "msrpc"),
+    (139, "netbios-ssn"),
+    (143, "imap"),
+    (161, "snmp"),
+    (179, "bgp"),
+    (389, "ldap"),
+    (443, "https"),
+    (445, "microsoft-ds"),
+    (465, "smtps"),
+    (500, "isakmp"),
+    (502, "modbus"),
+    (514, "syslog"),
+    (515, "printer"),
+    (548, "afp"),
+    (554, "rtsp"),
+    (587, "submission"),
+    (623, "ipmi"),
+    (631, "ipp"),
+    (636, "ldaps"),
+    (873, "rsync"),
+    (902, "vmware-auth"),
+    (993, "imaps"),
+    (995, "pop3s"),
+    (1080, "socks"),
+    (1194, "openvpn"),
+    (1433, "ms-sql-s"),
+    (1521, "oracle"),
+    (1723, "pptp"),
+    (1883, "mqtt"),
+    (2049, "nfs"),
+    (2082, "cpanel"),
+    (2083, "cpanel-ssl"),
+    (2086, "whm"),
+    (2087, "whm-ssl"),
+    (2181, "zookeeper"),
+    (2375, "docker"),
+    (2376, "docker-tls"),
+    (2379, "etcd"),
+    (3000, "http-alt"),
+    (3128, "squid-http"),
+    (3268, "globalcatldap"),
+    (3306, "mysql"),
+    (3389, "ms-wbt-server"),
+    (4443, "https-alt"),
+    (4848, "glassfish"),
+    (5000, "upnp"),
+    (5060, "sip"),
+    (5061, "sip-tls"),
+    (5432, "postgresql"),
+    (5601, "kibana"),
+    (5672, "amqp"),
+    (5900, "vnc"),
+    (5984, "couchdb"),
+    (5985, "wsman"),
+    (5986, "wsmans"),
+    (6379, "redis"),
+    (6443, "kubernetes"),
+    (6667, "irc"),
+    (7001, "weblogic"),
+    (7474, "neo4j"),
+    (8000, "http-alt"),
+    (8008, "http"),
+    (8080, "http-proxy"),
+    (8081, "http-alt"),
+    (8086, "influxdb"),
+    (8088, "http-alt"),
+    (8443, "https-alt"),
+    (8883, "secure-mqtt"),
+    (8888, "http-alt"),
+    (9000, "http-alt"),
+    (9042, "cassandra"),
+    (9090, "http-alt"),
+    (9092, "kafka"),
+    (9100, "jetdirect"),
+    (9200, "elasticsearch"),
+    (9300, "elasticsearch-transport"),
+    (9418, "git"),
+    (10000, "webmin"),
+    (10250, "kubelet"),
+    (11211, "memcached"),
+    (15672, "rabbitmq-mgmt"),
+    (27017, "mongodb"),
+    (50000, "sap"),
+];
+
+/// Port to service name table: the bundled entries plus any user overrides
+pub struct ServiceTable {
+    names: HashMap<u16, String>,
+}
+
+impl ServiceTable {
+    pub fn bundled() -> Self {
+        ServiceTable {
+            names: BUNDLED_SERVICES.iter().map(|(port, name)| (*port, name.to_string())).collect(),
+        }
+    }
+
+    /// Add or replace entries from a file. Each line is `port name`
+    /// (`8081 billing-api`, the port may carry a `/tcp` suffix) or an
+    /// nmap-services line (`billing-api 8081/tcp`, non-TCP lines are skipped);
+    /// `#` starts a comment. Returns the number of entries read.
+    pub fn load_overrides(&mut self, path: &str) -> io::Result<usize> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut count = 0;
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // nmap-services also lists UDP and SCTP ports
+            if fields.iter().take(2).any(|f| f.ends_with("/udp") || f.ends_with("/sctp")) {
+                continue;
+            }
+            let port = |field: &str| field.strip_suffix("/tcp").unwrap_or(field).parse::<u16>().ok();
+            let entry = match fields.as_slice() {
+                [first, second, ..] => match (port(first), port(second)) {
+                    (Some(p), _) => Some((p, *second)),
+                    (None, Some(p)) => Some((p, *first)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            let Some((port, name)) = entry else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}:{}: expected 'port name' or 'name port/tcp'", path, n + 1),
+                ));
+            };
+            self.names.insert(port, name.to_string());
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    pub fn name(&self, port: u16) -> Option<&str> {
+        self.names.get(&port).map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, contents: &str) -> (ServiceTable, io::Result<usize>) {
+        let path = std::env::temp_dir().join(format!("qport-services-{}-{}.txt", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        let mut table = ServiceTable::bundled();
+        let result = table.load_overrides(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        (table, result)
+    }
+
+    #[test]
+    fn both_line_formats() {
+        let (table, count) = load(
+            "formats",
+            "# custom services\n\
+             8081 billing-api\n\
+             9001/tcp queue-admin\n\
+             metrics-exporter\t9100/tcp\t0.000152\t# Prometheus\n\
+             \n",
+        );
+        assert_eq!(count.unwrap(), 3);
+        assert_eq!(table.name(8081), Some("billing-api"));
+        assert_eq!(table.name(9001), Some("queue-admin"));
+        assert_eq!(table.name(9100), Some("metrics-exporter"));
+    }
+
+    #[test]
+    fn non_tcp_lines_are_skipped() {
+        let (table, count) = load("protocols", "alt-snmp 40161/udp 0.433\nsctp-svc 49899/sctp\n12345 custom\n");
+        assert_eq!(count.unwrap(), 1);
+        assert_eq!(table.name(40161), None);
+        assert_eq!(table.name(49899), None);
+        assert_eq!(table.name(12345), Some("custom"));
+    }
+
+    #[test]
+    fn overrides_replace_bundled_names() {
+        let bundled = ServiceTable::bundled();
+        assert_eq!(bundled.name(22), Some("ssh"));
+        let (table, _) = load("override", "22 bastion-ssh\n");
+        assert_eq!(table.name(22), Some("bastion-ssh"));
+    }
+
+    #[test]
+    fn bad_line_reports_its_number() {
+        let (_, result) = load("bad", "8081 billing-api\n# fine\nnot-a-port-line\n");
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().ends_with(":3: expected 'port name' or 'name port/tcp'"));
+    }
+}
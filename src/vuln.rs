@@ -0,0 +1,285 @@
+use crate::record::HostRecord;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+/// What the local dataset knows about one CVE
+#[derive(Deserialize, Default, Clone)]
+pub struct CveInfo {
+    #[serde(alias = "cveID", alias = "cve")]
+    pub id: String,
+    #[serde(default, alias = "cvss3", alias = "baseScore")]
+    pub cvss: Option<f32>,
+    #[serde(default, alias = "vulnerabilityName", alias = "summary")]
+    pub title: Option<String>,
+    /// Listed in CISA's Known Exploited Vulnerabilities catalog
+    #[serde(default)]
+    pub kev: bool,
+}
+
+/// Offline CVE details merged from one or more JSON files
+#[derive(Default)]
+pub struct CveDb {
+    entries: HashMap<String, CveInfo>,
+}
+
+impl CveDb {
+    /// Load a JSON array of `{"id", "cvss", "title", "kev"}` entries, or the
+    /// CISA KEV catalog as published. Entries for the same CVE from several
+    /// files are merged, so scores and KEV status can come from different files.
+    pub fn load(&mut self, path: &str) -> io::Result<()> {
+        let invalid = |e: serde_json::Error| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e));
+        let value: Value = serde_json::from_reader(BufReader::new(File::open(path)?)).map_err(invalid)?;
+        // The KEV catalog wraps its entries in {"catalogVersion": ..., "vulnerabilities": [...]}
+        let (entries, all_kev) = match value {
+            Value::Object(mut catalog) => (catalog.remove("vulnerabilities").unwrap_or_default(), catalog.contains_key("catalogVersion")),
+            entries => (entries, false),
+        };
+        let entries: Vec<CveInfo> = serde_json::from_value(entries).map_err(invalid)?;
+
+        for entry in entries {
+            let existing = self.entries.entry(entry.id.to_uppercase()).or_insert_with(|| CveInfo {
+                id: entry.id.to_uppercase(),
+                ..Default::default()
+            });
+            existing.cvss = existing.cvss.or(entry.cvss);
+            existing.title = existing.title.take().or(entry.title);
+            existing.kev |= entry.kev || all_kev;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&CveInfo> {
+        self.entries.get(&id.to_uppercase())
+    }
+}
+
+/// `--cve` / `--min-cvss` selection of the CVEs worth reporting
+pub struct VulnFilter {
+    pub cves: Vec<String>,
+    pub min_cvss: Option<f32>,
+}
+
+impl VulnFilter {
+    pub fn is_active(&self) -> bool {
+        !self.cves.is_empty() || self.min_cvss.is_some()
+    }
+
+    /// A CVE matches when it is one of `--cve` (if given) and scores at least
+    /// `--min-cvss` (if given). CVEs without a known score never pass `--min-cvss`.
+    pub fn matches(&self, id: &str, db: &CveDb) -> bool {
+        let listed = self.cves.is_empty() || self.cves.iter().any(|cve| cve.eq_ignore_ascii_case(id));
+        let scored = match self.min_cvss {
+            Some(min) => db.get(id).and_then(|info| info.cvss).is_some_and(|score| score >= min),
+            None => true,
+        };
+        listed && scored
+    }
+}
+
+/// Write every matching CVE with the host:port pairs it affects, highest
+/// CVSS first. Returns the number of CVEs written.
+pub fn write_report(path: &str, records: &HashMap<String, HostRecord>, db: &CveDb, filter: &VulnFilter) -> io::Result<usize> {
+    let mut affected: HashMap<String, BTreeSet<String>> = HashMap::new();
+    for record in records.values() {
+        for ip_record in &record.ips {
+            let result = &ip_record.result;
+            for cve in result.vulns.iter().filter(|cve| filter.matches(cve, db)) {
+                let targets = affected.entry(cve.to_uppercase()).or_default();
+                if result.ports.is_empty() {
+                    targets.insert(record.host.clone());
+                }
+                for port in &result.ports {
+                    targets.insert(crate::format_host_port(&record.host, *port));
+                }
+            }
+        }
+    }
+
+    let mut cves: Vec<(&String, Option<&CveInfo>)> = affected.keys().map(|id| (id, db.get(id))).collect();
+    cves.sort_by(|(a_id, a), (b_id, b)| {
+        let a_score = a.and_then(|info| info.cvss).unwrap_or(-1.0);
+        let b_score = b.and_then(|info| info.cvss).unwrap_or(-1.0);
+        b_score.total_cmp(&a_score).then_with(|| a_id.cmp(b_id))
+    });
+
+    let mut file = BufWriter::new(File::create(path)?);
+    for (id, info) in &cves {
+        let mut line = id.to_string();
+        match info.and_then(|info| info.cvss) {
+            Some(score) => line.push_str(&format!(" [CVSS {:.1}]", score)),
+            None => line.push_str(" [CVSS ?]"),
+        }
+        if info.is_some_and(|info| info.kev) {
+            line.push_str(" [KEV]");
+        }
+        if let Some(title) = info.and_then(|info| info.title.as_deref()) {
+            line.push_str(&format!(" {}", title));
+        }
+        writeln!(file, "{}", line)?;
+        for target in &affected[*id] {
+            writeln!(file, "  {}", target)?;
+        }
+    }
+    file.flush()?;
+    Ok(cves.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::scan_from_json;
+    use serde_json::json;
+
+    fn write_temp(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("qport-vuln-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn load(files: &[(&str, serde_json::Value)]) -> CveDb {
+        let mut db = CveDb::default();
+        for (name, contents) in files {
+            let path = write_temp(name, &contents.to_string());
+            db.load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn load_plain_array_and_kev_catalog() {
+        let db = load(&[
+            (
+                "scores.json",
+                json!([
+                    { "id": "cve-2021-44228", "cvss": 10.0, "title": "Log4Shell" },
+                    { "cve": "CVE-2020-0001", "baseScore": 5.5 },
+                ]),
+            ),
+            (
+                "kev.json",
+                json!({
+                    "catalogVersion": "2024.01.01",
+                    "vulnerabilities": [
+                        { "cveID": "CVE-2021-44228", "vulnerabilityName": "Apache Log4j2 RCE", "vendorProject": "Apache" },
+                        { "cveID": "CVE-2019-0708", "vulnerabilityName": "BlueKeep" },
+                    ]
+                }),
+            ),
+        ]);
+        assert_eq!(db.len(), 3);
+
+        // Score and title from the first file, KEV status from the catalog
+        let log4shell = db.get("CVE-2021-44228").unwrap();
+        assert_eq!(log4shell.id, "CVE-2021-44228");
+        assert_eq!(log4shell.cvss, Some(10.0));
+        assert_eq!(log4shell.title.as_deref(), Some("Log4Shell"));
+        assert!(log4shell.kev);
+
+        let bluekeep = db.get("cve-2019-0708").unwrap();
+        assert_eq!(bluekeep.cvss, None);
+        assert_eq!(bluekeep.title.as_deref(), Some("BlueKeep"));
+        assert!(bluekeep.kev);
+
+        let other = db.get("CVE-2020-0001").unwrap();
+        assert_eq!(other.cvss, Some(5.5));
+        assert!(!other.kev);
+    }
+
+    #[test]
+    fn merge_fills_missing_scores() {
+        let db = load(&[
+            ("kev-first.json", json!([{ "id": "CVE-2023-1", "kev": true }])),
+            ("scores-later.json", json!([{ "id": "CVE-2023-1", "cvss": 7.5, "kev": false }])),
+        ]);
+        let info = db.get("CVE-2023-1").unwrap();
+        assert_eq!(info.cvss, Some(7.5));
+        assert!(info.kev);
+    }
+
+    #[test]
+    fn load_rejects_malformed_files() {
+        let path = write_temp("bad.json", "{\"vulnerabilities\": 5}");
+        let err = CveDb::default().load(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with(&path));
+    }
+
+    #[test]
+    fn filter_matching() {
+        let db = load(&[("filter.json", json!([{ "id": "CVE-2021-44228", "cvss": 10.0 }, { "id": "CVE-2020-0001", "cvss": 5.5 }]))]);
+
+        let min_cvss = VulnFilter {
+            cves: vec![],
+            min_cvss: Some(7.0),
+        };
+        assert!(min_cvss.is_active());
+        assert!(min_cvss.matches("cve-2021-44228", &db));
+        assert!(!min_cvss.matches("CVE-2020-0001", &db));
+        // Unscored CVEs never pass --min-cvss, even at 0
+        assert!(!min_cvss.matches("CVE-1999-0001", &db));
+        let zero = VulnFilter {
+            cves: vec![],
+            min_cvss: Some(0.0),
+        };
+        assert!(!zero.matches("CVE-1999-0001", &db));
+
+        let listed = VulnFilter {
+            cves: vec!["cve-2020-0001".to_string()],
+            min_cvss: None,
+        };
+        assert!(listed.matches("CVE-2020-0001", &db));
+        assert!(!listed.matches("CVE-2021-44228", &db));
+
+        let none = VulnFilter {
+            cves: vec![],
+            min_cvss: None,
+        };
+        assert!(!none.is_active());
+        assert!(none.matches("CVE-1999-0001", &db));
+    }
+
+    #[test]
+    fn report_orders_by_score() {
+        let db = load(&[(
+            "report.json",
+            json!([
+                { "id": "CVE-2020-0001", "cvss": 5.5 },
+                { "id": "CVE-2021-44228", "cvss": 10.0, "title": "Log4Shell", "kev": true },
+            ]),
+        )]);
+        let records = scan_from_json(&[(
+            "a.example.com",
+            json!([{ "ip": "192.0.2.1", "ports": [443, 80], "hostnames": [], "vulns": ["CVE-1999-0001", "CVE-2020-0001", "CVE-2021-44228"] }]),
+        )]);
+        let filter = VulnFilter {
+            cves: vec![],
+            min_cvss: None,
+        };
+
+        let path = std::env::temp_dir().join(format!("qport-vuln-{}-report.txt", std::process::id()));
+        let written = write_report(path.to_str().unwrap(), &records, &db, &filter).unwrap();
+        let report = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(written, 3);
+        let headings: Vec<&str> = report.lines().filter(|line| !line.starts_with(' ')).collect();
+        assert_eq!(
+            headings,
+            vec![
+                "CVE-2021-44228 [CVSS 10.0] [KEV] Log4Shell",
+                "CVE-2020-0001 [CVSS 5.5]",
+                "CVE-1999-0001 [CVSS ?]",
+            ]
+        );
+        assert!(report.starts_with("CVE-2021-44228 [CVSS 10.0] [KEV] Log4Shell\n  a.example.com:443\n  a.example.com:80\n"));
+    }
+}
@@ -0,0 +1,132 @@
+use crate::diff::{self, ChangeEvent};
+use crate::webhook::WebhookSink;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Rescan the input on an interval and emit what changed
+#[derive(clap::Args)]
+pub struct WatchArgs {
+    /// Time between the end of one scan and the start of the next, e.g. 900, 30m, 6h or 1d
+    #[arg(long, value_name = "DURATION", default_value = "1h", value_parser = parse_interval)]
+    pub interval: Duration,
+
+    /// Stop after this many scans instead of running until interrupted
+    #[arg(long, value_name = "N")]
+    pub runs: Option<usize>,
+
+    /// Append change events as JSON lines to this file
+    #[arg(long, value_name = "EVENTS_FILE")]
+    pub events: Option<String>,
+
+    #[command(flatten)]
+    pub scan: crate::Args,
+}
+
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => value.split_at(i),
+        None => (value, "s"),
+    };
+    let number: u64 = number.parse().map_err(|_| format!("invalid interval '{}'", value))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("invalid interval unit in '{}', expected s, m, h or d", value)),
+    };
+    let seconds = number.checked_mul(multiplier).ok_or_else(|| format!("interval too large: '{}'", value))?;
+    if seconds == 0 {
+        return Err("interval must be greater than zero".to_string());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+async fn emit(args: &WatchArgs, webhook: Option<&WebhookSink>, events: &[ChangeEvent]) -> io::Result<()> {
+    for event in events {
+        println!("{}", event.to_line());
+    }
+    if let Some(events_filename) = &args.events {
+        let mut file = OpenOptions::new().create(true).append(true).open(events_filename)?;
+        for event in events {
+            writeln!(file, "{}", serde_json::to_string(event)?)?;
+        }
+    }
+    if let Some(sink) = webhook {
+        sink.send(events, args.scan.verbose).await;
+    }
+    Ok(())
+}
+
+pub async fn run(args: &WatchArgs) -> io::Result<()> {
+    // Only change events go to stdout
+    let mut scan_args = args.scan.clone();
+    scan_args.silent = true;
+    let mut previous = scan_args.baseline.take().map(|path| diff::load_scan(&path)).transpose()?;
+    // Changes are posted here, not by each scan
+    let webhook = scan_args
+        .webhook
+        .take()
+        .map(|url| WebhookSink::new(&url, scan_args.webhook_template.as_deref(), scan_args.webhook_retries, scan_args.webhook_batch))
+        .transpose()?;
+
+    let mut round = 0;
+    loop {
+        round += 1;
+        match crate::scan(&scan_args).await {
+            Ok(results) => {
+                match &previous {
+                    Some(previous) => {
+                        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+                        let events = diff::compare(previous, &results).events(timestamp);
+                        if !events.is_empty() {
+                            emit(args, webhook.as_ref(), &events).await?;
+                        }
+                        if scan_args.verbose {
+                            eprintln!("Scan {} finished: {} changes", round, events.len());
+                        }
+                    }
+                    None => eprintln!("Scan {} finished: baseline of {} hosts", round, results.len()),
+                }
+                previous = Some(results);
+            }
+            // Keep watching; the next run may succeed
+            Err(e) => eprintln!("✗ Scan {} failed: {}", round, e),
+        }
+        if args.runs.is_some_and(|runs| round >= runs) {
+            return Ok(());
+        }
+        tokio::time::sleep(args.interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_interval_units() {
+        assert_eq!(parse_interval("900"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_interval("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_interval("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_interval("6h"), Ok(Duration::from_secs(21600)));
+        assert_eq!(parse_interval(" 1d "), Ok(Duration::from_secs(86400)));
+    }
+
+    #[test]
+    fn parse_interval_rejects() {
+        assert_eq!(parse_interval("0"), Err("interval must be greater than zero".to_string()));
+        assert_eq!(parse_interval("0h"), Err("interval must be greater than zero".to_string()));
+        assert_eq!(parse_interval("5x"), Err("invalid interval unit in '5x', expected s, m, h or d".to_string()));
+        assert_eq!(parse_interval("1h30m"), Err("invalid interval unit in '1h30m', expected s, m, h or d".to_string()));
+        assert_eq!(parse_interval("h"), Err("invalid interval 'h'".to_string()));
+        assert_eq!(parse_interval(""), Err("invalid interval ''".to_string()));
+        assert_eq!(
+            parse_interval("9999999999999999d"),
+            Err("interval too large: '9999999999999999d'".to_string())
+        );
+        assert!(parse_interval("99999999999999999999").is_err());
+    }
+}
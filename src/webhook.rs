@@ -0,0 +1,251 @@
+use crate::diff::ChangeEvent;
+use reqwest::{Client, StatusCode};
+use std::fs;
+use std::io;
+use std::time::Duration;
+
+/// Posts change events to a webhook, in batches, optionally shaped by a JSON template
+pub struct WebhookSink {
+    client: Client,
+    url: String,
+    template: Option<String>,
+    retries: u32,
+    batch_size: usize,
+}
+
+/// Fill a template's placeholders for one batch: `"{{events}}"` (quotes
+/// included) becomes the JSON array of events, `{{count}}` the number of
+/// events and `{{text}}` one line per event, escaped for use inside a JSON string.
+/// Only the template is scanned, so placeholders inside event data stay as they are.
+fn render(template: &str, events: &[ChangeEvent]) -> Result<String, serde_json::Error> {
+    let text: Vec<String> = events.iter().map(ChangeEvent::to_line).collect();
+    let text = serde_json::to_string(&text.join("\n"))?;
+    let values = [
+        ("\"{{events}}\"", serde_json::to_string(events)?),
+        ("{{count}}", events.len().to_string()),
+        ("{{text}}", text[1..text.len() - 1].to_string()),
+    ];
+
+    let mut body = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some((at, placeholder, value)) = values
+        .iter()
+        .filter_map(|(placeholder, value)| rest.find(placeholder).map(|at| (at, placeholder, value)))
+        .min_by_key(|(at, _, _)| *at)
+    {
+        body.push_str(&rest[..at]);
+        body.push_str(value);
+        rest = &rest[at + placeholder.len()..];
+    }
+    body.push_str(rest);
+    // Only send what parses as JSON
+    serde_json::from_str::<serde_json::Value>(&body)?;
+    Ok(body)
+}
+
+impl WebhookSink {
+    pub fn new(url: &str, template_path: Option<&str>, retries: u32, batch_size: usize) -> io::Result<Self> {
+        let template = template_path.map(fs::read_to_string).transpose()?;
+        if let (Some(template), Some(path)) = (&template, template_path) {
+            render(template, &[]).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))?;
+        }
+        let client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .map_err(io::Error::other)?;
+        Ok(WebhookSink {
+            client,
+            url: url.to_string(),
+            template,
+            retries,
+            batch_size: batch_size.max(1),
+        })
+    }
+
+    /// Send `events` in batches, retrying each batch with exponential backoff
+    /// on connection errors, 429 and 5xx. Returns the number of events delivered.
+    pub async fn send(&self, events: &[ChangeEvent], verbose: bool) -> usize {
+        let mut delivered = 0;
+        for batch in events.chunks(self.batch_size) {
+            let body = match &self.template {
+                Some(template) => render(template, batch),
+                None => serde_json::to_string(batch),
+            };
+            let body = match body {
+                Ok(body) => body,
+                Err(e) => {
+                    eprintln!("✗ Webhook template produced invalid JSON: {}", e);
+                    continue;
+                }
+            };
+            match self.post(body, verbose).await {
+                Ok(()) => delivered += batch.len(),
+                Err(e) => eprintln!("✗ Webhook {} failed: {}", self.url, e),
+            }
+        }
+        delivered
+    }
+
+    async fn post(&self, body: String, verbose: bool) -> Result<(), String> {
+        let mut attempt = 0;
+        loop {
+            let error = match self
+                .client
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .body(body.clone())
+                .send()
+                .await
+            {
+                Ok(resp) if resp.status().is_success() => return Ok(()),
+                Ok(resp) => {
+                    let status = resp.status();
+                    if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                        return Err(format!("HTTP {}", status));
+                    }
+                    format!("HTTP {}", status)
+                }
+                Err(e) => e.to_string(),
+            };
+            if attempt >= self.retries {
+                return Err(format!("{} after {} retries", error, self.retries));
+            }
+            let delay = Duration::from_secs(1 << attempt.min(6));
+            if verbose {
+                eprintln!("Webhook {} failed ({}), retrying in {}s", self.url, error, delay.as_secs());
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{HostChange, ScanDiff};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn new_hosts(hosts: &[&str]) -> Vec<ChangeEvent> {
+        let diff = ScanDiff {
+            new_hosts: hosts.iter().map(|h| h.to_string()).collect(),
+            ..Default::default()
+        };
+        diff.events(1_700_000_000)
+    }
+
+    /// An HTTP receiver answering with `statuses` in turn (200 once they run
+    /// out) and keeping the body of every request
+    async fn receiver(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let bodies = Arc::new(Mutex::new(vec![]));
+        let received = bodies.clone();
+        tokio::spawn(async move {
+            let mut statuses = statuses.into_iter();
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![];
+                let mut buf = [0; 4096];
+                let body = loop {
+                    let n = stream.read(&mut buf).await.unwrap_or(0);
+                    if n == 0 {
+                        break None;
+                    }
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    let Some((head, body)) = text.split_once("\r\n\r\n") else { continue };
+                    let length = head
+                        .lines()
+                        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break Some(body.to_string());
+                    }
+                };
+                let Some(body) = body else { continue };
+                received.lock().unwrap().push(body);
+                let status = statuses.next().unwrap_or(200);
+                let response = format!("HTTP/1.1 {} Test\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (url, bodies)
+    }
+
+    #[test]
+    fn render_fills_placeholders() {
+        let diff = ScanDiff {
+            new_tags: vec![HostChange {
+                host: "a.example.com".to_string(),
+                value: "say \"hi\"\\now".to_string(),
+            }],
+            ..Default::default()
+        };
+        let events = diff.events(1_700_000_000);
+        let body = render(r#"{"text": "Changes: {{text}}", "count": {{count}}, "events": "{{events}}"}"#, &events).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["text"], "Changes: 2023-11-14T22:13:20Z new_tag a.example.com say \"hi\"\\now");
+        assert_eq!(value["count"], 1);
+        assert_eq!(value["events"][0]["event"], "new_tag");
+        assert_eq!(value["events"][0]["value"], "say \"hi\"\\now");
+
+        // Lines are joined with an escaped newline
+        let body = render(r#"{"text": "{{text}}"}"#, &new_hosts(&["a.example.com", "b.example.com"])).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["text"], "2023-11-14T22:13:20Z new_host a.example.com\n2023-11-14T22:13:20Z new_host b.example.com");
+
+        assert!(render(r#"{"events": {{events}}}"#, &[]).is_err());
+    }
+
+    #[test]
+    fn render_leaves_placeholders_in_event_data() {
+        let diff = ScanDiff {
+            new_tags: vec![HostChange {
+                host: "a.example.com".to_string(),
+                value: "{{count}} {{text}} \"{{events}}\"".to_string(),
+            }],
+            ..Default::default()
+        };
+        let events = diff.events(1_700_000_000);
+        let body = render(r#"{"events": "{{events}}", "text": "{{text}}", "count": {{count}}}"#, &events).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(value["events"][0]["value"], "{{count}} {{text}} \"{{events}}\"");
+        assert_eq!(value["text"], "2023-11-14T22:13:20Z new_tag a.example.com {{count}} {{text}} \"{{events}}\"");
+        assert_eq!(value["count"], 1);
+    }
+
+    #[tokio::test]
+    async fn sends_events_in_batches() {
+        let (url, bodies) = receiver(vec![]).await;
+        let sink = WebhookSink::new(&url, None, 0, 2).unwrap();
+        let events = new_hosts(&["a.example.com", "b.example.com", "c.example.com", "d.example.com", "e.example.com"]);
+        assert_eq!(sink.send(&events, false).await, 5);
+
+        let bodies = bodies.lock().unwrap();
+        let sizes: Vec<usize> = bodies
+            .iter()
+            .map(|body| serde_json::from_str::<Vec<serde_json::Value>>(body).unwrap().len())
+            .collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let (url, bodies) = receiver(vec![500]).await;
+        let sink = WebhookSink::new(&url, None, 1, 10).unwrap();
+        assert_eq!(sink.send(&new_hosts(&["a.example.com"]), false).await, 1);
+        let bodies = bodies.lock().unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_eq!(bodies[0], bodies[1]);
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_client_errors() {
+        let (url, bodies) = receiver(vec![400]).await;
+        let sink = WebhookSink::new(&url, None, 3, 10).unwrap();
+        assert_eq!(sink.send(&new_hosts(&["a.example.com"]), false).await, 0);
+        assert_eq!(bodies.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::dns::DnsResolver;
+use clap::ValueEnum;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+// Random labels resolved per parent zone to learn its wildcard answers
+const PROBES_PER_ZONE: usize = 2;
+
+/// What to do with hosts that only resolve to their parent zone's wildcard
+#[derive(Clone, Copy, ValueEnum)]
+pub enum WildcardMode {
+    /// Keep the host in the output, flagged, but don't query its IPs
+    Mark,
+    /// Leave the host out of the output entirely
+    Drop,
+}
+
+/// Detects wildcard DNS per parent zone, caching each zone's wildcard IPs.
+/// Shared by the resolution tasks: each zone is probed once, and checks
+/// arriving while it is being probed wait for that result.
+#[derive(Default)]
+pub struct WildcardDetector {
+    zones: Mutex<HashMap<String, Arc<OnceCell<HashSet<IpAddr>>>>>,
+}
+
+impl WildcardDetector {
+    /// Return the parent zone if every one of `ips` is an answer that zone's
+    /// wildcard also gives for random labels
+    pub async fn check(&self, resolver: &DnsResolver, host: &str, ips: &[IpAddr]) -> Option<String> {
+        if ips.is_empty() || host.parse::<IpAddr>().is_ok() {
+            return None;
+        }
+        let (_, parent) = host.trim_end_matches('.').split_once('.')?;
+        // Don't probe directly under a TLD
+        if !parent.contains('.') {
+            return None;
+        }
+        let parent = parent.to_lowercase();
+
+        let zone = self.zones.lock().unwrap().entry(parent.clone()).or_default().clone();
+        let wildcard_ips = zone.get_or_init(|| probe(resolver, &parent)).await;
+        if !wildcard_ips.is_empty() && ips.iter().all(|ip| wildcard_ips.contains(ip)) {
+            Some(parent)
+        } else {
+            None
+        }
+    }
+}
+
+/// Resolve random labels under `zone` and collect what its wildcard answers
+async fn probe(resolver: &DnsResolver, zone: &str) -> HashSet<IpAddr> {
+    let mut wildcard_ips = HashSet::new();
+    for _ in 0..PROBES_PER_ZONE {
+        let label: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(|c| (c as char).to_ascii_lowercase())
+            .collect();
+        if let Ok(resolution) = resolver.resolve(&format!("{}.{}", label, zone)).await {
+            wildcard_ips.extend(resolution.ips);
+        }
+    }
+    wildcard_ips
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::tests::{from_servers, stub};
+    use crate::dns::ResolverMode;
+    use hickory_resolver::config::LookupIpStrategy;
+
+    #[tokio::test]
+    async fn detects_hosts_answering_like_the_wildcard() {
+        let wildcard_ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let server = stub(vec![wildcard_ip]).await;
+        let resolver = from_servers("wildcard", &[server], ResolverMode::Failover, LookupIpStrategy::Ipv4Only);
+        let detector = WildcardDetector::default();
+
+        let ips = [wildcard_ip];
+        let (a, b) = tokio::join!(
+            detector.check(&resolver, "a.wild.test", &ips),
+            detector.check(&resolver, "b.wild.test", &ips),
+        );
+        assert_eq!(a.as_deref(), Some("wild.test"));
+        assert_eq!(b.as_deref(), Some("wild.test"));
+        assert_eq!(detector.zones.lock().unwrap().len(), 1);
+
+        let other: IpAddr = "192.0.2.2".parse().unwrap();
+        assert_eq!(detector.check(&resolver, "c.wild.test", &[wildcard_ip, other]).await, None);
+        // Never probed directly under a TLD, nor for IP literals
+        assert_eq!(detector.check(&resolver, "wild.test", &[wildcard_ip]).await, None);
+        assert_eq!(detector.check(&resolver, "192.0.2.1", &[wildcard_ip]).await, None);
+    }
+}